@@ -0,0 +1,131 @@
+//! Subcommands that work on the tag database directly and exit, for
+//! scripts, e.g. `tidy tag receipt.pdf receipts 2021`.
+
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use structopt::StructOpt;
+
+use crate::{query::TagExpr, storage::Storage};
+
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+    /// Add tags to a file or directory
+    Tag {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a file or directory
+    Untag {
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+        #[structopt(required = true)]
+        tags: Vec<String>,
+    },
+    /// Print the paths whose tags match an expression, one per line
+    ///
+    /// Tags can be combined with `and`, `or`, `not` and parentheses, e.g.
+    /// `tidy find 'receipts and (2020 or 2021) and not paid'`. Tags next to
+    /// each other must all be present.
+    Find {
+        #[structopt(required = true)]
+        expr: Vec<String>,
+    },
+}
+
+impl Subcommand {
+    /// Whether the subcommand changes the database.
+    pub fn mutates(&self) -> bool {
+        matches!(self, Subcommand::Tag { .. } | Subcommand::Untag { .. })
+    }
+}
+
+pub fn run(command: Subcommand, storage: &dyn Storage) -> Result<(), Box<dyn Error>> {
+    match command {
+        // Stored the way the TUI lists them, so they show up there.
+        Subcommand::Tag { path, tags } => {
+            let path = path
+                .canonicalize()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            storage.add_tags(&path, &tags)
+        }
+        // Untagging a path that's gone is how its records get cleaned up.
+        Subcommand::Untag { path, tags } => {
+            let path = path.canonicalize().unwrap_or(path);
+            for tag in &tags {
+                storage.remove_tag(&path, tag)?;
+            }
+            Ok(())
+        }
+        Subcommand::Find { expr } => {
+            let expr = TagExpr::parse(&expr.join(" "))?;
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            for path in find(&expr, storage)? {
+                // A closed pipe, like `| head`, isn't worth an error.
+                if writeln!(out, "{}", path.display()).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Tagged paths whose tags `expr` matches.
+fn find(expr: &TagExpr, storage: &dyn Storage) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    Ok(storage
+        .all_tags()?
+        .into_iter()
+        .filter(|(_, tags)| expr.matches(tags))
+        .map(|(path, _)| path)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use std::{env, path::Path};
+
+    #[test]
+    fn tags_are_added_and_removed_by_path() {
+        let storage = MemoryStorage::default();
+        let path = env::temp_dir().canonicalize().unwrap();
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect();
+        let tag = Subcommand::Tag {
+            path: path.clone(),
+            tags: tags(&["a", "b"]),
+        };
+        run(tag, &storage).unwrap();
+        let untag = Subcommand::Untag {
+            path: path.clone(),
+            tags: tags(&["a"]),
+        };
+        run(untag, &storage).unwrap();
+        assert_eq!(storage.tags_for(&path).unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn find_lists_matching_paths() {
+        let storage = MemoryStorage::default();
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        storage
+            .add_tags(Path::new("/a"), &tags(&["receipts", "2021"]))
+            .unwrap();
+        storage
+            .add_tags(Path::new("/b"), &tags(&["receipts"]))
+            .unwrap();
+        storage.add_tags(Path::new("/c"), &tags(&["2021"])).unwrap();
+
+        let found = |expr| find(&TagExpr::parse(expr).unwrap(), &storage).unwrap();
+        assert_eq!(found("receipts"), [Path::new("/a"), Path::new("/b")]);
+        assert_eq!(found("receipts and not 2021"), [Path::new("/b")]);
+        assert_eq!(found("2021"), [Path::new("/a"), Path::new("/c")]);
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    cmp::Reverse, collections::HashMap, fs, path::Path, path::PathBuf, process, time::Duration,
+};
+
+use chrono::format::{Item, StrftimeItems};
+
+use crate::{filter, filter::Stat, paths, theme::Theme, view::ColumnKind};
+
+/// Name of the per-directory view configuration file.
+pub const DIR_CONFIG: &str = ".tidy";
+
+/// Yields the `key = value` pairs of a config file, skipping comments.
+pub fn entries(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim()))
+}
+
+pub fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// A metadata field shown in the info panel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InfoField {
+    Type,
+    Size,
+    Permissions,
+    Owner,
+    Created,
+    Accessed,
+    Modified,
+    /// A hash of the contents, for spotting copies.
+    Hash,
+    Tags,
+}
+
+impl InfoField {
+    fn parse(value: &str) -> Option<InfoField> {
+        match value {
+            "type" => Some(InfoField::Type),
+            "size" => Some(InfoField::Size),
+            "permissions" => Some(InfoField::Permissions),
+            "owner" => Some(InfoField::Owner),
+            "created" => Some(InfoField::Created),
+            "accessed" => Some(InfoField::Accessed),
+            "modified" => Some(InfoField::Modified),
+            "hash" => Some(InfoField::Hash),
+            "tags" => Some(InfoField::Tags),
+            _ => None,
+        }
+    }
+}
+
+/// Global settings read from the platform config directory (or `--config`),
+/// in the same `key = value` format as `.tidy` files, e.g.
+///
+/// ```text
+/// info = tags, size, permissions, hash, modified
+/// time_format = %Y-%m-%d %H:%M
+/// wrap_cursor = false
+/// tick_rate_ms = 500
+/// view = grid
+/// preview = true
+/// preview_lines = 40
+/// dirs_first = true
+/// max_depth = 3
+/// density = compact
+/// open_with = gimp, feh, code --new-window
+/// max_read_size = 100M
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    /// Fields shown in the info panel, in order.
+    pub info: Vec<InfoField>,
+    /// A chrono `strftime` format for timestamps.
+    pub time_format: String,
+    /// Set by `--read-only`; refuses anything that would change files or tags.
+    pub read_only: bool,
+    /// Whether moving past either end of a list wraps around or stops there.
+    pub wrap_cursor: bool,
+    /// Set from `--no-color` and `NO_COLOR`.
+    pub theme: Theme,
+    /// How long to wait for input before redrawing progress and statuses.
+    pub tick_rate: Duration,
+    /// The file list layout tidy starts in.
+    pub view: View,
+    /// Whether the preview pane starts out shown.
+    pub preview: bool,
+    /// How many lines of the highlighted file the preview pane reads.
+    pub preview_lines: usize,
+    /// Whether directories are listed before files until toggled with `D`,
+    /// after which the last choice is remembered instead.
+    pub dirs_first: bool,
+    /// How many levels down the recursive listing goes.
+    pub max_depth: usize,
+    /// How much room the panels leave around the file list.
+    pub density: Density,
+    /// Programs offered for opening a file with `X`, each with any arguments
+    /// that go before the file.
+    pub open_with: Vec<String>,
+    /// Files larger than this many bytes are never read whole, e.g. to hash
+    /// them.
+    pub max_read_size: u64,
+    /// Programs bound to keys with `command.<key> = <template>` lines.
+    pub commands: Vec<CustomCommand>,
+    /// When deleting asks first.
+    pub confirm: Confirm,
+    /// Whether delete and yank take a doubled key, like vim's `dd` and `yy`.
+    pub vim_keys: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            info: vec![
+                InfoField::Tags,
+                InfoField::Created,
+                InfoField::Accessed,
+                InfoField::Modified,
+            ],
+            time_format: "%a %b %e %T %Y".to_string(),
+            read_only: false,
+            wrap_cursor: true,
+            theme: Theme::default(),
+            tick_rate: tick_rate(250),
+            view: View::List,
+            preview: false,
+            preview_lines: 20,
+            dirs_first: false,
+            max_depth: 5,
+            density: Density::Normal,
+            open_with: vec![],
+            max_read_size: 100 << 20,
+            commands: vec![],
+            confirm: Confirm::Always,
+            vim_keys: false,
+        }
+    }
+}
+
+/// No terminal is tall enough to show more than this.
+const MAX_PREVIEW_LINES: usize = 1000;
+
+/// Converts a tick rate in milliseconds, kept between a busy loop and a UI
+/// that visibly lags.
+pub fn tick_rate(millis: u64) -> Duration {
+    Duration::from_millis(millis.clamp(16, 5000))
+}
+
+impl Config {
+    pub fn load(path: Option<PathBuf>) -> Config {
+        path.or_else(paths::config_file)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| Config::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    fn parse(contents: &str) -> Config {
+        let mut config = Config::default();
+        for (key, value) in entries(contents) {
+            match key {
+                "info" => config.info = list(value).filter_map(InfoField::parse).collect(),
+                // An invalid format would panic when displayed, so keep the default.
+                "time_format" if StrftimeItems::new(value).all(|item| item != Item::Error) => {
+                    config.time_format = value.to_string()
+                }
+                "tick_rate_ms" => {
+                    if let Ok(millis) = value.parse() {
+                        config.tick_rate = tick_rate(millis);
+                    }
+                }
+                "view" => {
+                    if let Some(view) = View::parse(value) {
+                        config.view = view;
+                    }
+                }
+                "preview" => {
+                    if let Ok(preview) = value.parse() {
+                        config.preview = preview;
+                    }
+                }
+                "preview_lines" => {
+                    if let Ok(lines) = value.parse::<usize>() {
+                        config.preview_lines = lines.min(MAX_PREVIEW_LINES);
+                    }
+                }
+                "dirs_first" => {
+                    if let Ok(dirs_first) = value.parse() {
+                        config.dirs_first = dirs_first;
+                    }
+                }
+                "max_depth" => {
+                    if let Ok(depth) = value.parse::<usize>() {
+                        config.max_depth = depth.max(1);
+                    }
+                }
+                "max_read_size" => {
+                    if let Ok(size) = filter::parse_size(value) {
+                        config.max_read_size = size;
+                    }
+                }
+                "open_with" => config.open_with = list(value).map(str::to_string).collect(),
+                "density" => {
+                    if let Some(density) = Density::parse(value) {
+                        config.density = density;
+                    }
+                }
+                "confirm" => {
+                    if let Some(confirm) = Confirm::parse(value) {
+                        config.confirm = confirm;
+                    }
+                }
+                "vim_keys" => {
+                    if let Ok(vim_keys) = value.parse() {
+                        config.vim_keys = vim_keys;
+                    }
+                }
+                "wrap_cursor" => {
+                    if let Ok(wrap) = value.parse() {
+                        config.wrap_cursor = wrap;
+                    }
+                }
+                _ => {
+                    if let Some(command) = key
+                        .strip_prefix("command.")
+                        .and_then(|key| CustomCommand::parse(key, value))
+                    {
+                        config.commands.retain(|bound| bound.key != command.key);
+                        config.commands.push(command);
+                    }
+                }
+            }
+        }
+        config
+    }
+}
+
+/// How the file list is laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    List,
+    /// Several entries per row, for wide terminals.
+    Grid,
+    /// One row per entry with metadata columns.
+    Table,
+}
+
+impl View {
+    pub fn parse(value: &str) -> Option<View> {
+        match value {
+            "list" => Some(View::List),
+            "grid" => Some(View::Grid),
+            "table" => Some(View::Table),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            View::List => "list",
+            View::Grid => "grid",
+            View::Table => "table",
+        }
+    }
+}
+
+/// How tightly screens are laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Density {
+    Normal,
+    /// No margins, a single line for commands and the info panel hidden
+    /// until toggled, for small terminals.
+    Compact,
+}
+
+impl Density {
+    fn parse(value: &str) -> Option<Density> {
+        match value {
+            "normal" => Some(Density::Normal),
+            "compact" => Some(Density::Compact),
+            _ => None,
+        }
+    }
+
+    /// The margin around a screen, given its margin at normal density.
+    pub fn margin(self, normal: u16) -> u16 {
+        match self {
+            Density::Normal => normal,
+            Density::Compact => 0,
+        }
+    }
+}
+
+/// A program run on the highlighted entry by a key of the user's choosing,
+/// e.g. `command.z = convert {path} {path}.png`. Keys tidy already uses
+/// keep their own meaning.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomCommand {
+    pub key: char,
+    /// Whitespace separated words, where `{path}`, `{name}` and `{dir}`
+    /// stand for the entry's path, file name and directory. Each word is
+    /// one argument, so paths with spaces need no quoting.
+    pub template: String,
+}
+
+impl CustomCommand {
+    fn parse(key: &str, template: &str) -> Option<CustomCommand> {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next(), template.is_empty()) {
+            (Some(key), None, false) => Some(CustomCommand {
+                key,
+                template: template.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The program's name, to describe the command by.
+    pub fn program(&self) -> &str {
+        self.template.split_whitespace().next().unwrap_or_default()
+    }
+
+    /// The command line for `path`, run from its directory.
+    pub fn command(&self, path: &Path) -> process::Command {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let fill = |word: &str| {
+            word.replace("{path}", &path.to_string_lossy())
+                .replace(
+                    "{name}",
+                    &path.file_name().unwrap_or_default().to_string_lossy(),
+                )
+                .replace("{dir}", &dir.to_string_lossy())
+        };
+        let mut words = self.template.split_whitespace().map(fill);
+        let mut command = process::Command::new(words.next().unwrap_or_default());
+        command.args(words).current_dir(dir);
+        command
+    }
+}
+
+/// When a destructive command asks before going ahead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Confirm {
+    Always,
+    /// Only when acting on more than this many entries.
+    Above(usize),
+}
+
+impl Confirm {
+    /// Parses `always` or a number of entries.
+    fn parse(value: &str) -> Option<Confirm> {
+        match value {
+            "always" => Some(Confirm::Always),
+            count => count.parse().ok().map(Confirm::Above),
+        }
+    }
+
+    /// Whether acting on `count` entries needs a yes first.
+    pub fn asks(self, count: usize) -> bool {
+        match self {
+            Confirm::Always => true,
+            Confirm::Above(limit) => count > limit,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    fn parse(value: &str) -> Option<SortKey> {
+        match value {
+            "name" => Some(SortKey::Name),
+            "size" => Some(SortKey::Size),
+            "modified" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+
+    /// Sorts by the metadata read with the listing, largest and newest
+    /// first.
+    pub fn sort(self, files: &mut [PathBuf], stats: &HashMap<PathBuf, Stat>) {
+        match self {
+            SortKey::Name => files.sort_by_key(|file| file.file_name().map(|n| n.to_owned())),
+            SortKey::Size => {
+                files.sort_by_key(|file| Reverse(stats.get(file).and_then(|stat| stat.size)))
+            }
+            SortKey::Modified => {
+                files.sort_by_key(|file| Reverse(stats.get(file).and_then(|stat| stat.modified)))
+            }
+        }
+    }
+}
+
+/// View settings read from a `.tidy` file when entering a directory.
+///
+/// The file is a list of `key = value` lines, e.g.
+///
+/// ```text
+/// sort = size
+/// columns = name, size
+/// hidden = *.o, target
+/// gitignore = true
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DirConfig {
+    pub sort: Option<SortKey>,
+    /// Table columns to show here instead of the remembered ones.
+    pub columns: Vec<ColumnKind>,
+    pub hidden: Vec<String>,
+    /// Whether to also hide what the directory's `.gitignore` names.
+    pub gitignore: bool,
+}
+
+impl DirConfig {
+    pub fn load(directory: &Path) -> DirConfig {
+        let mut config = fs::read_to_string(directory.join(DIR_CONFIG))
+            .map(|contents| DirConfig::parse(&contents))
+            .unwrap_or_default();
+        if config.gitignore {
+            if let Ok(contents) = fs::read_to_string(directory.join(".gitignore")) {
+                config.hidden.extend(gitignore_patterns(&contents));
+            }
+        }
+        config
+    }
+
+    fn parse(contents: &str) -> DirConfig {
+        let mut config = DirConfig::default();
+        for (key, value) in entries(contents) {
+            match key {
+                "sort" => config.sort = SortKey::parse(value),
+                "columns" => config.columns = list(value).filter_map(ColumnKind::parse).collect(),
+                "hidden" => config.hidden = list(value).map(str::to_string).collect(),
+                "gitignore" => config.gitignore = value.parse().unwrap_or(false),
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// Whether `file` should be left out of the listing.
+    pub fn hides(&self, file: &Path) -> bool {
+        let name = match file.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => return false,
+        };
+        self.hidden.iter().any(|pattern| glob_match(pattern, name))
+    }
+}
+
+/// The name patterns of a `.gitignore`. Negations and patterns anchored to a
+/// path below the directory aren't supported, so they're left out.
+fn gitignore_patterns(contents: &str) -> impl Iterator<Item = String> + '_ {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(|line| line.trim_start_matches('/').trim_end_matches('/'))
+        .filter(|pattern| !pattern.is_empty() && !pattern.contains('/'))
+        .map(str::to_string)
+}
+
+/// Matches `name` against a shell-style pattern supporting `*` and `?`.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_commands_fill_in_the_path() {
+        let config = Config::parse(
+            "command.z = convert {path} {name}.png\ncommand.zz = ignored\ncommand.b =",
+        );
+        assert_eq!(config.commands.len(), 1);
+        let command = config.commands[0].command(Path::new("/photos/my cat.heic"));
+        assert_eq!(command.get_program(), "convert");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["/photos/my cat.heic", "my cat.heic.png"]);
+        assert_eq!(command.get_current_dir(), Some(Path::new("/photos")));
+    }
+
+    #[test]
+    fn dir_config_reads_columns_and_gitignore() {
+        let directory = std::env::temp_dir().join(format!("tidy-dir-config-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join(DIR_CONFIG),
+            "columns = name, bogus, size\ngitignore = true",
+        )
+        .unwrap();
+        fs::write(
+            directory.join(".gitignore"),
+            "# build output\n/target/\n*.o\n!keep.o\nsrc/generated.rs\n",
+        )
+        .unwrap();
+        let config = DirConfig::load(&directory);
+
+        assert_eq!(config.columns, [ColumnKind::Name, ColumnKind::Size]);
+        assert_eq!(config.hidden, ["target", "*.o"]);
+        assert!(config.hides(Path::new("/x/target")));
+        assert!(config.hides(Path::new("/x/main.o")));
+        assert!(!config.hides(Path::new("/x/keep.c")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn sorting_uses_the_stats_read_with_the_listing() {
+        let stat = |size| Stat {
+            modified: None,
+            size,
+        };
+        let stats: HashMap<_, _> = vec![
+            (PathBuf::from("/small"), stat(Some(1))),
+            (PathBuf::from("/big"), stat(Some(9))),
+            (PathBuf::from("/dir"), stat(None)),
+        ]
+        .into_iter()
+        .collect();
+        let mut files: Vec<_> = ["/small", "/dir", "/big"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        SortKey::Size.sort(&mut files, &stats);
+        assert_eq!(
+            files,
+            [Path::new("/big"), Path::new("/small"), Path::new("/dir")]
+        );
+    }
+}
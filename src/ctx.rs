@@ -1,192 +1,1763 @@
 use std::{
-    any::TypeId,
+    collections::HashMap,
+    env,
     fs::{self, Metadata},
-    io,
-    path::PathBuf,
-    time::SystemTime,
+    io::{self, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use chrono::{DateTime, Utc};
-use crossterm::{
-    event::{KeyCode, KeyEvent},
-    terminal,
-};
+use chrono::{DateTime, Local, TimeZone};
+use crossterm::event::{KeyCode, KeyEvent};
 use tui::{
     backend::CrosstermBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
     widgets::{
-        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table, Tabs,
-        Wrap,
+        Block, BorderType, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table,
+        TableState, Wrap,
     },
-    Terminal,
 };
 
-use crate::{Command, DirInfo, Msg, Signal};
+use crate::{
+    config::{Config, Density, InfoField, View},
+    filter::{Filter, Stat},
+    git::GitStatus,
+    ops::{CopyItem, Operation, Summary},
+    paths, reveal,
+    storage::Storage,
+    theme::Theme,
+    view::{ColumnKind, Columns},
+    Command, DirInfo, Msg, Signal,
+};
+
+/// Names each context, for switching to it and sending it messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContextId {
+    Main,
+    Tagging,
+    Goto,
+    Search,
+    Log,
+    Filter,
+    Summary,
+    Note,
+    OpenWith,
+    Rename,
+}
 
 pub trait Ctx {
-    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stdout>>, di: DirInfo);
-    fn handle_key(&mut self, key: KeyEvent, di: DirInfo) -> Option<Signal>;
+    fn id(&self) -> ContextId;
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, di: &DirInfo);
+    fn handle_key(&mut self, key: KeyEvent, di: &DirInfo) -> Option<Signal>;
     fn send(&mut self, msg: Msg);
+    /// The entry this context is pointing at, if any.
+    fn current_file(&self, _di: &DirInfo) -> Option<PathBuf> {
+        None
+    }
+    /// The file list layout this context shows, if it has one.
+    fn view(&self) -> Option<View> {
+        None
+    }
+    /// A signal raised while rendering, collected after each frame.
+    fn take_signal(&mut self) -> Option<Signal> {
+        None
+    }
+}
+
+/// A key, what it does and how it's described in the hints. Keys with an
+/// empty hint work but aren't listed, like the arrow-key twins of `hjkl`.
+type Binding = (KeyCode, Command, &'static str);
+
+/// Shown on the first run only, for the keys nobody can do without.
+const ONBOARDING_HINT: &str =
+    " Welcome! (j/k) move (Enter) details (t) tag (:) go to (q) quit (Esc) dismiss";
+
+/// Keys of the file list, in the order they're hinted.
+const MAIN_KEYS: &[Binding] = &[
+    (KeyCode::Char('t'), Command::Tag, "tag"),
+    (KeyCode::Char('T'), Command::TagAll, "tag all"),
+    (KeyCode::Char('.'), Command::TagAgain, "repeat tag"),
+    (KeyCode::Char('N'), Command::Note, "note"),
+    (KeyCode::Char('r'), Command::Rename, "rename"),
+    (KeyCode::Char('e'), Command::Edit, "edit"),
+    (KeyCode::Char('y'), Command::Yank, "yank"),
+    (KeyCode::Char('p'), Command::Paste, "paste"),
+    (KeyCode::Char('d'), Command::Delete, "delete"),
+    (KeyCode::Char('K'), Command::ToggleProtected, "protect"),
+    (KeyCode::Char(':'), Command::Goto, "goto"),
+    (KeyCode::Char('/'), Command::Search, "search"),
+    (KeyCode::Char('\''), Command::TypeAhead, "type to jump"),
+    (KeyCode::Char('f'), Command::Filter, "filter"),
+    (KeyCode::Char('u'), Command::ToggleUntagged, "untagged"),
+    (KeyCode::Char('v'), Command::ToggleView, "view"),
+    (KeyCode::Char('M'), Command::ToggleLong, "long list"),
+    (KeyCode::Char('D'), Command::ToggleDirsFirst, "dirs first"),
+    (KeyCode::Char('R'), Command::ToggleRecursive, "recursive"),
+    (KeyCode::Char('o'), Command::TogglePreview, "preview"),
+    (KeyCode::Char('i'), Command::ToggleInfo, "info panel"),
+    (
+        KeyCode::Char('C'),
+        Command::ToggleCommands,
+        "commands panel",
+    ),
+    (KeyCode::Enter, Command::Expand, "details"),
+    (KeyCode::Tab, Command::FocusPanel(true), "next panel"),
+    (KeyCode::BackTab, Command::FocusPanel(false), ""),
+    (KeyCode::Char('L'), Command::Log, "log"),
+    (KeyCode::Char('I'), Command::Reindex, "reindex"),
+    (KeyCode::Char(' '), Command::Select, "select"),
+    (KeyCode::Char('x'), Command::Open, "open"),
+    (KeyCode::Char('X'), Command::OpenWith, "open with"),
+    (KeyCode::Char('O'), Command::Reveal, "open in file manager"),
+    (KeyCode::Char('!'), Command::Shell, "shell"),
+    (
+        KeyCode::Char('c'),
+        Command::Pending('c'),
+        "then n/p/r copy name/path/relative",
+    ),
+    (KeyCode::Char('q'), Command::Quit, "quit"),
+    (KeyCode::Char('P'), Command::TogglePaths, ""),
+    (KeyCode::Char('~'), Command::Home, ""),
+    (KeyCode::Char('g'), Command::Pending('g'), ""),
+    (KeyCode::Esc, Command::Cancel, ""),
+    (KeyCode::Char('k'), Command::CursorUp, ""),
+    (KeyCode::Up, Command::CursorUp, ""),
+    (KeyCode::Char('j'), Command::CursorDown, ""),
+    (KeyCode::Down, Command::CursorDown, ""),
+    (KeyCode::Char('h'), Command::CursorLeft, ""),
+    (KeyCode::Left, Command::CursorLeft, ""),
+    (KeyCode::Char('l'), Command::CursorRight, ""),
+    (KeyCode::Right, Command::CursorRight, ""),
+];
+
+/// Keys that only do something in the table view, and only hinted there.
+const TABLE_KEYS: &[Binding] = &[
+    (KeyCode::Char('['), Command::FocusColumn(false), ""),
+    (
+        KeyCode::Char(']'),
+        Command::FocusColumn(true),
+        "next column",
+    ),
+    (KeyCode::Char('+'), Command::ResizeColumn(2), "wider"),
+    (KeyCode::Char('='), Command::ResizeColumn(2), ""),
+    (KeyCode::Char('-'), Command::ResizeColumn(-2), "narrower"),
+    (
+        KeyCode::Char('1'),
+        Command::ToggleColumn(0),
+        "show/hide column 1-4",
+    ),
+    (KeyCode::Char('2'), Command::ToggleColumn(1), ""),
+    (KeyCode::Char('3'), Command::ToggleColumn(2), ""),
+    (KeyCode::Char('4'), Command::ToggleColumn(3), ""),
+];
+
+/// Keys of the log and summary lists.
+const LIST_KEYS: &[Binding] = &[
+    (KeyCode::Char('j'), Command::CursorDown, "down"),
+    (KeyCode::Down, Command::CursorDown, ""),
+    (KeyCode::Char('k'), Command::CursorUp, "up"),
+    (KeyCode::Up, Command::CursorUp, ""),
+    (KeyCode::Esc, Command::Cancel, "close"),
+    (KeyCode::Char('q'), Command::Cancel, ""),
+    (KeyCode::Char('L'), Command::Cancel, ""),
+    (KeyCode::Enter, Command::Cancel, ""),
+];
+
+/// Keys of the prompts besides the typed characters themselves, which go to
+/// the input.
+const TAGGING_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "save"),
+    (KeyCode::Tab, "accept suggestion"),
+    (KeyCode::Backspace, "remove tag"),
+    (KeyCode::Esc, "cancel"),
+];
+const RENAME_KEYS: &[(KeyCode, &str)] = &[(KeyCode::Enter, "rename"), (KeyCode::Esc, "cancel")];
+const NOTE_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "save, empty to remove"),
+    (KeyCode::Esc, "cancel"),
+];
+const GOTO_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Tab, "complete"),
+    (KeyCode::Enter, "go"),
+    (KeyCode::Esc, "cancel"),
+];
+const FILTER_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Enter, "apply, empty to clear"),
+    (KeyCode::Esc, "cancel"),
+];
+const SEARCH_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::Tab, "next"),
+    (KeyCode::BackTab, "previous"),
+    (KeyCode::Up, "older query"),
+    (KeyCode::Down, "newer query"),
+    (KeyCode::Enter, "go"),
+    (KeyCode::Right, "reveal"),
+    (KeyCode::Esc, "back"),
+];
+
+/// The command bound to `key`, if any.
+fn lookup<'a>(keymap: impl IntoIterator<Item = &'a Binding>, key: KeyCode) -> Command {
+    keymap
+        .into_iter()
+        .find(|(bound, _, _)| *bound == key)
+        .map_or(Command::None, |&(_, command, _)| command)
+}
+
+/// Lists keys as `(key) what it does`, skipping those without a hint.
+fn hint_line<'a>(keys: impl IntoIterator<Item = (KeyCode, &'a str)>) -> String {
+    labelled_hints(keys.into_iter().map(|(key, hint)| (key_label(key), hint)))
+}
+
+/// Like `hint_line`, for keys whose label isn't just the key, like `dd`.
+fn labelled_hints<'a>(keys: impl IntoIterator<Item = (String, &'a str)>) -> String {
+    keys.into_iter()
+        .filter(|(_, hint)| !hint.is_empty())
+        .map(|(label, hint)| format!("({}) {}", label, hint))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn list_hints() -> String {
+    hint_line(LIST_KEYS.iter().map(|&(key, _, hint)| (key, hint)))
+}
+
+fn key_label(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        key => format!("{:?}", key),
+    }
 }
 
 pub struct MainContext {
     pub file_list_state: ListState,
+    /// Entries picked with Space for commands that act on several at once.
     pub selection: Vec<PathBuf>,
+    pub yanked: Vec<PathBuf>,
+    /// Entries waiting on a yes to be deleted.
+    pub pending_delete: Option<Vec<PathBuf>>,
+    pub pending_paste: Option<PastePlan>,
+    /// The first key of a sequence like `g/`, and when it was pressed.
+    pub pending_key: Option<(char, Instant)>,
+    pub path_display: PathDisplay,
+    /// The directory tidy was started in, for copying relative paths.
+    pub start_dir: PathBuf,
+    /// The last entry found missing while drawing, so it's only refreshed for
+    /// once.
+    pub vanished: Option<PathBuf>,
+    pub signal: Option<Signal>,
+    pub storage: Rc<dyn Storage>,
+    pub view: View,
+    /// Entries per row as of the last grid render.
+    pub grid_columns: usize,
+    /// First row shown in the grid and table views.
+    pub row_offset: usize,
+    pub columns: Columns,
+    /// The entry whose details are shown beneath it in the list view.
+    pub expanded: Option<PathBuf>,
+    pub show_preview: bool,
+    /// What's been typed to jump to an entry by name and when the last key
+    /// came, as the jump ends after a pause.
+    pub type_ahead: Option<(String, Instant)>,
+    /// Whether the list view shows sizes and dates beside each entry.
+    pub long: bool,
+    pub show_info: bool,
+    pub show_commands: bool,
+    /// The panel the movement keys scroll, when it's shown.
+    pub focus: Panel,
+    /// Lines scrolled past at the top of the preview and info panels.
+    pub preview_scroll: u16,
+    pub info_scroll: u16,
+    /// Whether the first-run key summary is shown, until the first
+    /// navigation or Esc.
+    pub onboarding: bool,
+    /// The last file previewed and what was read from it, so it isn't
+    /// re-read every frame.
+    pub preview: Option<(PathBuf, String)>,
+    pub lookups: Lookups,
+    pub config: Rc<Config>,
+}
+
+/// Storage lookups for the header and info panel, kept until the listing is
+/// refreshed or what they read changes, rather than made every frame.
+#[derive(Default)]
+pub struct Lookups {
+    header: Option<HeaderNotes>,
+    details: HashMap<PathBuf, Option<Vec<Spans<'static>>>>,
+    /// The selection the summary was made for, and the summary.
+    selection: Option<(Vec<PathBuf>, Vec<Spans<'static>>)>,
+    /// Content hashes worked out so far.
+    hashes: HashMap<PathBuf, String>,
+    hashing: HashMap<PathBuf, Hashing>,
+}
+
+impl Lookups {
+    fn clear(&mut self) {
+        *self = Lookups::default();
+    }
+}
+
+/// A content hash being worked out on its own thread, given up on when
+/// dropped.
+struct Hashing {
+    receiver: mpsc::Receiver<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Hashing {
+    fn start(file: PathBuf, limit: u64) -> Hashing {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        thread::spawn(move || {
+            if let Some(hash) = hash_str(&file, limit, &flag) {
+                let _ = sender.send(hash);
+            }
+        });
+        Hashing { receiver, cancel }
+    }
+}
+
+impl Drop for Hashing {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// What the header says about the directory beyond the listing itself.
+#[derive(Clone)]
+struct HeaderNotes {
+    untagged: usize,
+    /// `None` when the index couldn't be read.
+    indexed: Option<Option<SystemTime>>,
+    last_operation: Option<(SystemTime, String)>,
+}
+
+/// Entries whose details are kept before the cache starts over.
+const DETAILS_CACHE_SIZE: usize = 256;
+
+/// A paste waiting on the user to settle name clashes, one at a time.
+#[derive(Default)]
+pub struct PastePlan {
+    ready: Vec<CopyItem>,
+    /// Entries whose destination exists, in the order they're asked about.
+    clashes: Vec<(PathBuf, PathBuf)>,
+}
+
+/// The parts of the main screen that can take the focus.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Panel {
+    Files,
+    Preview,
+    Info,
+}
+
+/// The panel after (or before) `focus` among the shown ones, coming round
+/// to the file list at the ends.
+fn cycle_focus(focus: Panel, forward: bool, preview: bool, info: bool) -> Panel {
+    let shown: Vec<_> = [
+        (Panel::Files, true),
+        (Panel::Preview, preview),
+        (Panel::Info, info),
+    ]
+    .iter()
+    .filter(|(_, shown)| *shown)
+    .map(|(panel, _)| *panel)
+    .collect();
+    let at = shown.iter().position(|panel| *panel == focus).unwrap_or(0);
+    let next = match forward {
+        true => (at + 1) % shown.len(),
+        false => (at + shown.len() - 1) % shown.len(),
+    };
+    shown[next]
+}
+
+/// How entries are labelled in the file list.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PathDisplay {
+    Name,
+    Relative,
+    Absolute,
+}
+
+impl PathDisplay {
+    fn next(self) -> PathDisplay {
+        match self {
+            PathDisplay::Name => PathDisplay::Relative,
+            PathDisplay::Relative => PathDisplay::Absolute,
+            PathDisplay::Absolute => PathDisplay::Name,
+        }
+    }
+
+    fn label(self, file: &Path, directory: &Path) -> String {
+        let label = match self {
+            PathDisplay::Name => file.file_name().map(Path::new),
+            PathDisplay::Relative => file.strip_prefix(directory).ok(),
+            PathDisplay::Absolute => None,
+        };
+        label.unwrap_or(file).display().to_string()
+    }
+}
+
+impl MainContext {
+    /// Git marker, icon and label of an entry in the file list, with the
+    /// label shortened to fit in `width` columns.
+    fn entry_spans(&self, file: &Path, state: &DirInfo, width: usize) -> Vec<Span<'static>> {
+        let icon = match file.is_dir() {
+            true => "📁",
+            false => "📄",
+        };
+        let git = match state.git.get(file) {
+            Some(status) => Span::styled(
+                format!("{} ", status.marker()),
+                self.config.theme.fg(match status {
+                    GitStatus::Staged => Color::Green,
+                    GitStatus::Modified => Color::Yellow,
+                    GitStatus::Untracked => Color::Red,
+                }),
+            ),
+            None => Span::raw(""),
+        };
+        let icon = match state.protected.contains(file) {
+            true => format!("🔒{}", icon),
+            false => icon.to_string(),
+        };
+        let mark = match self.selection.contains(&file.to_path_buf()) {
+            true => Span::styled("* ", self.config.theme.accent(Color::Magenta)),
+            false => Span::raw(""),
+        };
+        // Names alone are ambiguous across a whole subtree or a path list.
+        let flattened = state.recursive.is_some() || state.listed.is_some();
+        let display = match (flattened, self.path_display) {
+            (true, PathDisplay::Name) => PathDisplay::Relative,
+            (_, display) => display,
+        };
+        let label = display.label(file, Path::new(&state.path));
+        let room =
+            width.saturating_sub(mark.width() + git.width() + Span::raw(icon.as_str()).width());
+        vec![
+            mark,
+            git,
+            Span::raw(format!("{}{}", icon, truncate_middle(&label, room))),
+        ]
+    }
+
+    /// An entry followed by its size and modification time, padded so they
+    /// line up down the list like `ls -l`.
+    fn long_spans(&self, file: &Path, state: &DirInfo, width: usize) -> Vec<Span<'static>> {
+        let mut spans = self.entry_spans(file, state, width.saturating_sub(LONG_META_WIDTH));
+        let used: usize = spans.iter().map(Span::width).sum();
+        spans.push(Span::raw(
+            " ".repeat(width.saturating_sub(LONG_META_WIDTH + used)),
+        ));
+        spans.push(Span::styled(
+            long_meta(state.stats.get(file)),
+            self.config.theme.fg(Color::DarkGray),
+        ));
+        spans
+    }
+
+    /// Tags, image size, link target and metadata of `file`, or `None` if
+    /// it can't be read.
+    fn details(&self, file: &Path) -> Option<Vec<Spans<'static>>> {
+        // Fall back to the link itself when a symlink's target is missing.
+        let metadata = fs::metadata(file)
+            .or_else(|_| fs::symlink_metadata(file))
+            .ok()?;
+        let mut lines = vec![];
+        if let Ok(Some(note)) = self.storage.note(file) {
+            lines.push(Spans::from(format!("Note: {}", note)));
+        }
+        if let Some(image) = image_str(file) {
+            lines.push(Spans::from(image));
+        }
+        if let Some(link) = link_str(file) {
+            lines.push(Spans::from(link));
+        }
+        let tags = match self.config.info.contains(&InfoField::Tags) {
+            true => self.storage.tags_for(file).ok(),
+            false => None,
+        };
+        let seen = self
+            .storage
+            .first_seen(file)
+            .ok()
+            .flatten()
+            .and_then(local_time)
+            .map(|seen| format!("First seen: {}", seen.format(&self.config.time_format)));
+        let hash = self.lookups.hashes.get(file).map(String::as_str);
+        lines.extend(info_lines(
+            &self.config.info,
+            |field| field_str(field, &metadata, &self.config, hash),
+            tags.map(|tags| self.tags_line(&tags)),
+            seen,
+        ));
+        Some(lines)
+    }
+
+    /// `details`, looked up once per entry until something changes.
+    fn cached_details(&mut self, file: &Path) -> Option<Vec<Spans<'static>>> {
+        if self.lookups.details.len() >= DETAILS_CACHE_SIZE {
+            self.lookups.details.clear();
+        }
+        if !self.lookups.details.contains_key(file) {
+            let details = self.details(file);
+            self.lookups.details.insert(file.to_path_buf(), details);
+        }
+        if self.config.info.contains(&InfoField::Hash)
+            && !self.lookups.hashes.contains_key(file)
+            && !self.lookups.hashing.contains_key(file)
+        {
+            let hashing = Hashing::start(file.to_path_buf(), self.config.max_read_size);
+            self.lookups.hashing.insert(file.to_path_buf(), hashing);
+        }
+        self.lookups.details[file].clone()
+    }
+
+    /// Picks up finished hashes, so the details are looked up again with
+    /// them, and gives up on those no longer shown.
+    fn receive_hashes(&mut self, state: &DirInfo) {
+        let shown = [self.selected(state), self.expanded.as_ref()];
+        let lookups = &mut self.lookups;
+        lookups
+            .hashing
+            .retain(|file, _| shown.contains(&Some(file)));
+        let mut done = vec![];
+        for (file, hashing) in &lookups.hashing {
+            match hashing.receiver.try_recv() {
+                Ok(hash) => done.push((file.clone(), Some(hash))),
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => done.push((file.clone(), None)),
+            }
+        }
+        for (file, hash) in done {
+            lookups.hashing.remove(&file);
+            lookups.details.remove(&file);
+            lookups
+                .hashes
+                .insert(file, hash.unwrap_or_else(|| "-".to_string()));
+        }
+    }
+
+    fn header_notes(&mut self, state: &DirInfo) -> HeaderNotes {
+        if let Some(notes) = &self.lookups.header {
+            return notes.clone();
+        }
+        let directory = Path::new(&state.path);
+        let notes = HeaderNotes {
+            untagged: state
+                .entries
+                .iter()
+                .filter(|file| !state.tagged.contains(*file))
+                .count(),
+            indexed: self.storage.indexed_at(directory).ok(),
+            last_operation: self.storage.last_operation(directory).ok().flatten(),
+        };
+        self.lookups.header = Some(notes.clone());
+        notes
+    }
+
+    /// Title of the commands panel, with a clock that shows the loop is
+    /// alive and when the listing was last read from disk.
+    fn commands_title(&self, state: &DirInfo) -> String {
+        let mut title = match self.config.read_only {
+            true => "Commands 🔒 read-only".to_string(),
+            false => "Commands".to_string(),
+        };
+        title.push_str(&format!(" ─ {}", Local::now().format("%T")));
+        if let Some(loaded_at) = state.loaded_at {
+            title.push_str(&format!(
+                ", refreshed {}",
+                DateTime::<Local>::from(loaded_at).format("%T")
+            ));
+        }
+        title
+    }
+
+    fn tags_line(&self, tags: &[String]) -> Spans<'static> {
+        let mut line = vec![Span::raw("Tags: ")];
+        match tags.len() {
+            0 => line.push(Span::raw("none")),
+            n => {
+                line.extend(tag_pills(tags, self.config.theme));
+                line.push(Span::raw(format!("({})", n)));
+            }
+        }
+        Spans::from(line)
+    }
+
+    /// Count, total size and combined tags of the selection, to check what a
+    /// bulk command is about to act on.
+    fn selection_details(&mut self, state: &DirInfo) -> Vec<Spans<'static>> {
+        match &self.lookups.selection {
+            Some((selection, details)) if *selection == self.selection => details.clone(),
+            _ => {
+                let details = self.summarize_selection(state);
+                self.lookups.selection = Some((self.selection.clone(), details.clone()));
+                details
+            }
+        }
+    }
+
+    fn summarize_selection(&self, state: &DirInfo) -> Vec<Spans<'static>> {
+        let stats: Vec<_> = self
+            .selection
+            .iter()
+            .filter_map(|file| state.stats.get(file))
+            .collect();
+        let size: u64 = stats.iter().filter_map(|stat| stat.size).sum();
+        let directories = stats.iter().filter(|stat| stat.size.is_none()).count();
+        let mut summary = format!(
+            "{} selected, {} in total",
+            self.selection.len(),
+            human_size(size)
+        );
+        if directories > 0 {
+            summary.push_str(&format!(", not counting {} directories", directories));
+        }
+        let mut tags: Vec<String> = vec![];
+        for file in &self.selection {
+            for tag in self.storage.tags_for(file).unwrap_or_default() {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+        vec![Spans::from(summary), self.tags_line(&tags)]
+    }
+
+    /// The focused panel, which is the file list again once the panel that
+    /// had the focus is hidden.
+    fn focus(&self) -> Panel {
+        match self.focus {
+            Panel::Preview if !self.show_preview => Panel::Files,
+            Panel::Info if !self.show_info => Panel::Files,
+            focus => focus,
+        }
+    }
+
+    /// Border style of `panel`, marked when it has the focus among several.
+    fn border_style(&self, panel: Panel) -> Style {
+        match self.focus() == panel && (self.show_preview || self.show_info) {
+            true => self.config.theme.accent(Color::Yellow),
+            false => Style::default(),
+        }
+    }
+
+    /// The first lines of `file`, read again once the selection moves or
+    /// the listing is refreshed.
+    fn preview(&mut self, file: &Path) -> &str {
+        let stale = self.preview.as_ref().is_none_or(|(path, _)| path != file);
+        if stale {
+            // A refresh drops the text but shouldn't lose the place in it.
+            if self.preview.is_some() {
+                self.preview_scroll = 0;
+            }
+            // Only the first lines are read, however large the file.
+            let text = preview_str(file, self.config.preview_lines);
+            self.preview = Some((file.to_path_buf(), text));
+        }
+        self.preview.as_ref().map_or("", |(_, text)| text)
+    }
+
+    /// Lays entries out left to right in as many columns as the longest one
+    /// allows, scrolling by rows.
+    fn render_grid(
+        &mut self,
+        rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>,
+        area: Rect,
+        block: Block,
+        state: &DirInfo,
+    ) {
+        let inner = block.inner(area);
+        rect.render_widget(block, area);
+
+        let cells: Vec<_> = state
+            .files
+            .iter()
+            .map(|file| self.entry_spans(file, state, (inner.width as usize).saturating_sub(2)))
+            .collect();
+        let cell_width = |cell: &[Span]| cell.iter().map(Span::width).sum::<usize>();
+        let width = cells.iter().map(|cell| cell_width(cell)).max().unwrap_or(0) + 2;
+        let columns = (inner.width as usize / width).max(1);
+        let rows = (inner.height as usize).max(1);
+        self.grid_columns = columns;
+
+        let selected = self.file_list_state.selected();
+        self.scroll_to(selected.unwrap_or(0) / columns, rows);
+
+        let highlight = self.config.theme.highlight();
+        let lines: Vec<_> = cells
+            .chunks(columns)
+            .enumerate()
+            .skip(self.row_offset)
+            .take(rows)
+            .map(|(row, cells)| {
+                let mut spans = vec![];
+                for (column, cell) in cells.iter().enumerate() {
+                    let padding = " ".repeat(width - cell_width(cell));
+                    let is_selected = selected == Some(row * columns + column);
+                    spans.extend(cell.iter().cloned().map(|mut span| {
+                        if is_selected {
+                            span.style = span.style.patch(highlight);
+                        }
+                        span
+                    }));
+                    spans.push(Span::raw(padding));
+                }
+                Spans::from(spans)
+            })
+            .collect();
+        rect.render_widget(Paragraph::new(lines), inner);
+    }
+
+    /// One row per entry with the columns picked in `self.columns`.
+    fn render_table(
+        &mut self,
+        rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>,
+        area: Rect,
+        block: Block,
+        state: &DirInfo,
+    ) {
+        // Leave room for the header.
+        let rows = (block.inner(area).height as usize).saturating_sub(1).max(1);
+        let selected = self.file_list_state.selected();
+        self.scroll_to(selected.unwrap_or(0), rows);
+
+        let visible: Vec<_> = self
+            .columns
+            .visible_in(&state.config.columns)
+            .map(|(index, column)| (index, *column))
+            .collect();
+        let header = Row::new(visible.iter().map(|(index, column)| {
+            let mut style = Style::default().add_modifier(Modifier::BOLD);
+            if *index == self.columns.focused {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            Cell::from(format!("{} {}", index + 1, column.kind.title())).style(style)
+        }));
+        let body: Vec<_> = state
+            .files
+            .iter()
+            .skip(self.row_offset)
+            .take(rows)
+            .map(|file| {
+                Row::new(
+                    visible
+                        .iter()
+                        .map(|(_, column)| self.table_cell(column.kind, file, state)),
+                )
+            })
+            .collect();
+        let widths: Vec<_> = visible
+            .iter()
+            .map(|(_, column)| Constraint::Length(column.width))
+            .collect();
+        let table = Table::new(body)
+            .header(header)
+            .block(block)
+            .widths(&widths)
+            .column_spacing(1)
+            .highlight_style(self.config.theme.highlight());
+        let mut table_state = TableState::default();
+        table_state.select(selected.map(|selected| selected - self.row_offset));
+        rect.render_stateful_widget(table, area, &mut table_state);
+    }
+
+    fn table_cell(&self, kind: ColumnKind, file: &Path, state: &DirInfo) -> Cell<'static> {
+        let stat = state.stats.get(file);
+        match kind {
+            ColumnKind::Name => {
+                let width = self
+                    .columns
+                    .columns
+                    .iter()
+                    .find(|column| column.kind == ColumnKind::Name)
+                    .map_or(0, |column| column.width as usize);
+                Cell::from(Spans::from(self.entry_spans(file, state, width)))
+            }
+            ColumnKind::Size => Cell::from(
+                stat.and_then(|stat| stat.size)
+                    .map(human_size)
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            ColumnKind::Modified => Cell::from(
+                stat.and_then(|stat| stat.modified)
+                    .and_then(local_time)
+                    .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            ColumnKind::Tags => Cell::from(Spans::from(tag_pills(
+                &self.storage.tags_for(file).unwrap_or_default(),
+                self.config.theme,
+            ))),
+        }
+    }
+
+    fn hints(&self) -> String {
+        let table = match self.view {
+            View::Table => TABLE_KEYS,
+            _ => &[],
+        };
+        let read_only = self.config.read_only;
+        let vim_keys = self.config.vim_keys;
+        labelled_hints(
+            MAIN_KEYS
+                .iter()
+                .chain(table)
+                .filter(|(_, command, _)| !(read_only && command.mutates()))
+                .map(|&(key, command, hint)| match command {
+                    Command::Delete | Command::Yank if vim_keys => (key_label(key).repeat(2), hint),
+                    _ => (key_label(key), hint),
+                })
+                .chain(
+                    self.config
+                        .commands
+                        .iter()
+                        // Shadowed by a key of tidy's own.
+                        .filter(|custom| {
+                            lookup(
+                                MAIN_KEYS.iter().chain(TABLE_KEYS),
+                                KeyCode::Char(custom.key),
+                            ) == Command::None
+                        })
+                        .map(|custom| (custom.key.to_string(), custom.program())),
+                ),
+        )
+    }
+
+    fn save_columns(&self) -> Option<Signal> {
+        self.columns
+            .save()
+            .err()
+            .map(|e| Signal::Status(format!("Couldn't save columns: {}", e)))
+    }
+
+    /// Adjusts `row_offset` so `row` is among the `rows` shown.
+    fn scroll_to(&mut self, row: usize, rows: usize) {
+        if row < self.row_offset {
+            self.row_offset = row;
+        } else if row >= self.row_offset + rows {
+            self.row_offset = row + 1 - rows;
+        }
+    }
+
+    /// What a command acting on several entries applies to: the selection,
+    /// or the highlighted entry when nothing is selected.
+    fn targets(&self, state: &DirInfo) -> Vec<PathBuf> {
+        match self.selection.is_empty() {
+            true => self.selected(state).cloned().into_iter().collect(),
+            false => self.selection.clone(),
+        }
+    }
+
+    /// Starts deleting `paths`, letting go of the selection if that's what
+    /// they are.
+    fn delete(&mut self, paths: Vec<PathBuf>) -> Signal {
+        if paths == self.selection {
+            self.selection.clear();
+        }
+        Signal::Spawn(Operation::Delete(paths))
+    }
+
+    /// Asks about the next name clash of `plan`, or starts copying once
+    /// there are none left.
+    fn continue_paste(&mut self, mut plan: PastePlan) -> Option<Signal> {
+        if !plan.clashes.is_empty() {
+            self.pending_paste = Some(plan);
+            return None;
+        }
+        match plan.ready.is_empty() {
+            true => Some(Signal::Status("Nothing to paste".to_string())),
+            false => Some(Signal::Spawn(Operation::Copy(std::mem::take(
+                &mut plan.ready,
+            )))),
+        }
+    }
+
+    fn selected<'a>(&self, state: &'a DirInfo) -> Option<&'a PathBuf> {
+        self.file_list_state
+            .selected()
+            .and_then(|selected| state.files.get(selected))
+    }
+
+    fn command(&self, event: KeyEvent) -> Command {
+        match lookup(MAIN_KEYS.iter().chain(TABLE_KEYS), event.code) {
+            Command::Delete if self.config.vim_keys => Command::Pending('d'),
+            Command::Yank if self.config.vim_keys => Command::Pending('y'),
+            Command::None => self
+                .config
+                .commands
+                .iter()
+                .position(|custom| KeyCode::Char(custom.key) == event.code)
+                .map_or(Command::None, Command::Custom),
+            command => command,
+        }
+    }
 }
+
 impl Ctx for MainContext {
-    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stdout>>, state: DirInfo) {
+    fn id(&self) -> ContextId {
+        ContextId::Main
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, state: &DirInfo) {
+        self.receive_hashes(state);
         let size = rect.size();
+        let compact = self.config.density == Density::Compact;
+        // A pending question is shown even with the commands panel hidden.
+        let prompting = self.pending_delete.is_some() || self.pending_paste.is_some();
+        let show_commands = self.show_commands || prompting;
+        let mut constraints = vec![Constraint::Length(1), Constraint::Min(5)];
+        if self.onboarding {
+            constraints.push(Constraint::Length(1));
+        }
+        if show_commands {
+            constraints.push(Constraint::Length(if compact { 1 } else { 3 }));
+        }
+        if self.show_info {
+            constraints.push(Constraint::Length(5));
+        }
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .margin(1)
-            .constraints(
-                [
-                    Constraint::Min(5),
-                    Constraint::Length(3),
-                    Constraint::Length(4),
-                ]
-                .as_ref(),
-            )
+            .margin(self.config.density.margin(1))
+            .constraints(constraints)
             .split(size);
 
+        let title = match &state.listed {
+            Some(listed) => format!("{} ({} paths)", listed.source, listed.paths.len()),
+            None => state.path.clone(),
+        };
+        let mut header = vec![Span::styled(
+            title,
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        if !state.filter.is_empty() {
+            header.push(Span::styled(
+                format!("  filter: {}", state.filter),
+                self.config.theme.fg(Color::LightCyan),
+            ));
+        }
+        let theme = self.config.theme;
+        let notes = self.header_notes(state);
+        if notes.untagged > 0 && !state.loading {
+            header.push(Span::styled(
+                format!("  {} untagged", notes.untagged),
+                theme.fg(Color::Yellow),
+            ));
+        }
+        if !self.selection.is_empty() {
+            header.push(Span::styled(
+                format!("  {} selected", self.selection.len()),
+                theme.accent(Color::Magenta),
+            ));
+        }
+        if let Some(depth) = state.recursive {
+            header.push(Span::styled(
+                format!("  recursive, {} levels", depth),
+                theme.fg(Color::LightCyan),
+            ));
+        }
+        match notes.indexed {
+            _ if state.listed.is_some() => {}
+            Some(Some(indexed)) => header.push(Span::styled(
+                format!(
+                    "  indexed {}",
+                    ago(SystemTime::now()
+                        .duration_since(indexed)
+                        .unwrap_or_default())
+                ),
+                theme.fg(Color::DarkGray),
+            )),
+            Some(None) => header.push(Span::styled("  not indexed", theme.fg(Color::DarkGray))),
+            None => {}
+        }
+        if let (None, Some((at, outcome))) = (&state.listed, notes.last_operation) {
+            header.push(Span::styled(
+                format!(
+                    "  last {} {}",
+                    outcome,
+                    ago(SystemTime::now().duration_since(at).unwrap_or_default())
+                ),
+                theme.fg(Color::DarkGray),
+            ));
+        }
+        let header = Paragraph::new(Spans::from(header));
+        rect.render_widget(header, chunks[0]);
+
         let file_block = Block::default()
             .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White))
-            .title(state.path.clone())
+            .style(self.config.theme.fg(Color::White))
+            .border_style(self.border_style(Panel::Files))
+            .title(
+                match (
+                    state.loading,
+                    state.hidden(),
+                    self.file_list_state.selected(),
+                ) {
+                    (true, _, _) => format!("Files (loading {}…)", state.files.len()),
+                    (false, 0, None) => format!("Files ({})", state.files.len()),
+                    (false, 0, Some(selected)) => {
+                        format!("Files ({}/{})", selected + 1, state.files.len())
+                    }
+                    (false, hidden, None) => {
+                        format!("Files (showing {}, {} hidden)", state.files.len(), hidden)
+                    }
+                    (false, hidden, Some(selected)) => format!(
+                        "Files ({}/{}, {} hidden)",
+                        selected + 1,
+                        state.files.len(),
+                        hidden
+                    ),
+                },
+            )
             .border_type(BorderType::Plain);
-        let items: Vec<_> = state
-            .files
-            .iter()
-            .map(|file| {
-                let meta = fs::metadata(file).unwrap();
-                let icon = match meta.is_dir() {
-                    true => "📁",
-                    false => "📄",
+        let files_area = match self.show_preview {
+            true => {
+                let halves = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(chunks[1]);
+                let text = match self.selected(state).cloned() {
+                    Some(file) => self.preview(&file).to_string(),
+                    None => String::new(),
                 };
-                ListItem::new(Span::styled(
-                    format!("{}{}", icon, file.display()),
-                    Style::default(),
-                ))
-            })
-            .collect();
-        let list = List::new(items).block(file_block).highlight_style(
-            Style::default()
-                .bg(Color::Yellow)
-                .fg(Color::Black)
-                .add_modifier(Modifier::BOLD),
-        );
-        rect.render_stateful_widget(list, chunks[0], &mut self.file_list_state);
-        let mut info_str = String::new();
-        if let Some(selected) = self.file_list_state.selected() {
-            let file = &state.files[selected];
-            let metadata = fs::metadata(file).expect("Unable to open metadata for file.");
-            info_str = metadata_str(metadata);
+                // Kept to the text, so scrolling can't run past its end.
+                let lines = text.lines().count().saturating_sub(1) as u16;
+                self.preview_scroll = self.preview_scroll.min(lines);
+                let preview = Paragraph::new(text).scroll((self.preview_scroll, 0)).block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .style(self.config.theme.fg(Color::White))
+                        .border_style(self.border_style(Panel::Preview))
+                        .title("Preview")
+                        .border_type(BorderType::Plain),
+                );
+                rect.render_widget(preview, halves[1]);
+                halves[0]
+            }
+            false => chunks[1],
+        };
+        match self.view {
+            View::List => {
+                let width = file_block.inner(files_area).width as usize;
+                let expanded = self.expanded.clone().map(|file| self.cached_details(&file));
+                let items: Vec<_> = state
+                    .files
+                    .iter()
+                    .map(|file| {
+                        let mut lines = vec![Spans::from(match self.long {
+                            true => self.long_spans(file, state, width),
+                            false => self.entry_spans(file, state, width),
+                        })];
+                        if let (true, Some(details)) =
+                            (self.expanded.as_ref() == Some(file), &expanded)
+                        {
+                            let details = details
+                                .clone()
+                                .unwrap_or_else(|| vec![Spans::from("(gone)")]);
+                            let style = self.config.theme.fg(Color::LightCyan);
+                            lines.extend(details.into_iter().map(|mut line| {
+                                line.0.insert(0, Span::raw("    "));
+                                for span in &mut line.0 {
+                                    span.style = style.patch(span.style);
+                                }
+                                line
+                            }));
+                        }
+                        ListItem::new(lines)
+                    })
+                    .collect();
+                let list = List::new(items)
+                    .block(file_block)
+                    .highlight_style(self.config.theme.highlight());
+                rect.render_stateful_widget(list, files_area, &mut self.file_list_state);
+            }
+            View::Grid => self.render_grid(rect, files_area, file_block, state),
+            View::Table => self.render_table(rect, files_area, file_block, state),
+        }
+        if let Some((offset, length)) = scroll_thumb(
+            self.file_list_state.selected(),
+            state.files.len(),
+            files_area.height.saturating_sub(2),
+        ) {
+            // Drawn over the right border, which serves as the track.
+            let thumb = Rect {
+                x: files_area.right().saturating_sub(1),
+                y: files_area.y + 1 + offset,
+                width: 1,
+                height: length,
+            };
+            let lines = vec![Spans::from("█"); length as usize];
+            rect.render_widget(Paragraph::new(lines), thumb);
+        }
+        if state.loading {
+            // Over the bottom border, so nobody mistakes a partial listing
+            // for the whole directory.
+            let more = match &state.listed {
+                Some(listed) => format!(
+                    " +{} more loading ",
+                    listed.paths.len().saturating_sub(state.entries.len())
+                ),
+                None => " more loading… ".to_string(),
+            };
+            let notice = Rect {
+                x: files_area.x + 2,
+                y: files_area.bottom().saturating_sub(1),
+                width: (Span::raw(more.as_str()).width() as u16)
+                    .min(files_area.width.saturating_sub(4)),
+                height: 1,
+            };
+            let notice_widget =
+                Paragraph::new(more.as_str()).style(self.config.theme.fg(Color::Yellow));
+            rect.render_widget(notice_widget, notice);
+        }
+        let mut info_lines = vec![];
+        if !self.selection.is_empty() {
+            info_lines = self.selection_details(state);
+        } else if let Some(file) = self.selected(state).cloned() {
+            match self.cached_details(&file) {
+                Some(details) => info_lines = details,
+                // Removed since the listing was read.
+                None => {
+                    info_lines = vec![Spans::from("(gone)")];
+                    if self.vanished.as_ref() != Some(&file) {
+                        self.vanished = Some(file);
+                        self.signal = Some(Signal::Refresh);
+                    }
+                }
+            }
         }
 
-        let command_block = Paragraph::new("(t)ag").block(
-            Block::default()
-                .borders(Borders::ALL)
-                .style(Style::default().fg(Color::White))
-                .title("Commands")
-                .border_type(BorderType::Plain),
-        );
-        rect.render_widget(command_block, chunks[1]);
-        // let time = now.elapsed().unwrap().as_millis();
-        // info_str += &format!("\n Render Time: {}", time);
-        let info = Paragraph::new(info_str)
-            .style(Style::default().fg(Color::LightCyan))
+        let clash = self
+            .pending_paste
+            .as_ref()
+            .and_then(|plan| plan.clashes.first());
+        let typing = self
+            .type_ahead
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < TYPE_AHEAD_TIMEOUT);
+        let commands = match (&self.pending_delete, clash, &state.status) {
+            (Some(paths), _, _) => match paths.as_slice() {
+                [path] => format!("Delete {}? (y/n)", path.display()),
+                paths => format!("Delete {} entries? (y/n)", paths.len()),
+            },
+            _ if typing.is_some() => {
+                format!("Go to: {}", typing.map_or("", |(typed, _)| typed.as_str()))
+            }
+            (None, Some((_, to)), _) => format!(
+                "{} exists: (o)verwrite (s)kip (r)ename (Esc) cancel",
+                to.display()
+            ),
+            (None, None, Some(status)) => format!("{} | {}", self.hints(), status),
+            (None, None, None) => self.hints(),
+        };
+        let command_block = Paragraph::new(commands);
+        let command_block = match compact {
+            true => command_block,
+            false => command_block.block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(self.config.theme.fg(Color::White))
+                    .title(self.commands_title(state))
+                    .border_type(BorderType::Plain),
+            ),
+        };
+        let mut panels = chunks[2..].iter();
+        if let Some(area) = self.onboarding.then(|| panels.next()).flatten() {
+            let welcome = Paragraph::new(ONBOARDING_HINT).style(self.config.theme.highlight());
+            rect.render_widget(welcome, *area);
+        }
+        if let Some(area) = show_commands.then(|| panels.next()).flatten() {
+            rect.render_widget(command_block, *area);
+        }
+        // Wrapping makes the line count a guess, so this only stops at
+        // the last unwrapped line.
+        self.info_scroll = self
+            .info_scroll
+            .min(info_lines.len().saturating_sub(1) as u16);
+        let info = Paragraph::new(info_lines)
+            .style(self.config.theme.fg(Color::LightCyan))
             .wrap(Wrap { trim: true })
+            .scroll((self.info_scroll, 0))
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .style(Style::default().fg(Color::White))
+                    .style(self.config.theme.fg(Color::White))
+                    .border_style(self.border_style(Panel::Info))
                     .title("Info")
                     .border_type(BorderType::Plain),
             );
-        rect.render_widget(info, chunks[2]);
+        if let Some(area) = self.show_info.then(|| panels.next()).flatten() {
+            rect.render_widget(info, *area);
+        }
     }
 
-    fn handle_key(&mut self, event: KeyEvent, state: DirInfo) -> Option<Signal> {
-        let command = match event {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                ..
-            } => Command::Quit,
-
-            KeyEvent {
-                code: KeyCode::Up, ..
+    fn handle_key(&mut self, event: KeyEvent, state: &DirInfo) -> Option<Signal> {
+        if let Some(paths) = self.pending_delete.take() {
+            return match event.code {
+                KeyCode::Char('y') => Some(self.delete(paths)),
+                _ => None,
+            };
+        }
+        if let Some((typed, at)) = self.type_ahead.take() {
+            if let (KeyCode::Char(c), true) = (event.code, at.elapsed() < TYPE_AHEAD_TIMEOUT) {
+                let selected = self.file_list_state.selected().unwrap_or(0);
+                let (typed, found) = type_ahead(&state.files, selected, typed, c);
+                if let Some(index) = found {
+                    self.file_list_state.select(Some(index));
+                }
+                self.type_ahead = Some((typed, Instant::now()));
+                return None;
             }
-            | KeyEvent {
-                code: KeyCode::Char('k'),
-                ..
-            } => Command::CursorUp,
-
-            KeyEvent {
-                code: KeyCode::Char('j'),
-                ..
+            // Anything else ends it, and is handled as usual unless it's Esc.
+            if event.code == KeyCode::Esc {
+                return None;
+            }
+        }
+        if let Some(mut plan) = self.pending_paste.take() {
+            let (from, to) = plan.clashes.remove(0);
+            match event.code {
+                KeyCode::Char('o') => {
+                    if let Some(status) = protection(&*self.storage, std::slice::from_ref(&to)) {
+                        // Still up to the user to rename or skip it.
+                        plan.clashes.insert(0, (from, to));
+                        self.pending_paste = Some(plan);
+                        return Some(Signal::Status(status));
+                    }
+                    plan.ready.push(CopyItem {
+                        from,
+                        to,
+                        overwrite: true,
+                    })
+                }
+                KeyCode::Char('r') => plan.ready.push(CopyItem {
+                    from,
+                    to: paths::free_name(&to),
+                    overwrite: false,
+                }),
+                KeyCode::Char('s') => {}
+                _ => return Some(Signal::Status("Paste cancelled".to_string())),
             }
-            | KeyEvent {
-                code: KeyCode::Down,
-                ..
-            } => Command::CursorDown,
+            return self.continue_paste(plan);
+        }
 
-            KeyEvent {
-                code: KeyCode::Char('t'),
-                ..
-            } => Command::Tag,
+        let command = self
+            .pending_key
+            .take()
+            .filter(|(_, at)| at.elapsed() < PENDING_KEY_TIMEOUT)
+            .and_then(|(first, _)| key_sequence(first, event.code))
+            .unwrap_or_else(|| self.command(event));
 
-            _ => Command::None,
-        };
+        if self.config.read_only && command.mutates() {
+            return Some(Signal::Status("Read-only mode".to_string()));
+        }
 
         match command {
-            Command::CursorUp => {
-                if let Some(selected) = self.file_list_state.selected() {
-                    let len = state.files.len();
-                    if selected > 0 {
-                        self.file_list_state.select(Some(selected - 1));
-                    } else {
-                        self.file_list_state.select(Some(len - 1));
-                    }
-                }
+            Command::CursorUp | Command::CursorDown if self.focus() != Panel::Files => {
+                let scroll = match self.focus() {
+                    Panel::Preview => &mut self.preview_scroll,
+                    _ => &mut self.info_scroll,
+                };
+                *scroll = match command {
+                    Command::CursorUp => scroll.saturating_sub(1),
+                    _ => scroll.saturating_add(1),
+                };
             }
-            Command::CursorDown => {
+            Command::FocusPanel(forward) => {
+                self.focus = cycle_focus(self.focus(), forward, self.show_preview, self.show_info);
+            }
+            Command::CursorUp
+            | Command::CursorDown
+            | Command::CursorLeft
+            | Command::CursorRight => {
+                self.info_scroll = 0;
+                self.preview_scroll = 0;
                 if let Some(selected) = self.file_list_state.selected() {
                     let len = state.files.len();
-                    if selected >= len - 1 {
-                        self.file_list_state.select(Some(0));
-                    } else {
-                        self.file_list_state.select(Some(selected + 1));
-                    }
+                    let wrap = self.config.wrap_cursor;
+                    self.file_list_state.select(match (self.view, command) {
+                        (View::Grid, Command::CursorUp) => grid_up(selected, self.grid_columns),
+                        (View::Grid, Command::CursorDown) => {
+                            grid_down(selected, len, self.grid_columns)
+                        }
+                        (View::Grid, Command::CursorLeft) | (_, Command::CursorUp) => {
+                            cursor_up(selected, len, wrap)
+                        }
+                        (View::Grid, _) | (_, Command::CursorDown) => {
+                            cursor_down(selected, len, wrap)
+                        }
+                        _ => Some(selected),
+                    });
                 }
             }
+            Command::ToggleView => {
+                self.view = match self.view {
+                    View::List => View::Grid,
+                    View::Grid => View::Table,
+                    View::Table => View::List,
+                };
+            }
+            Command::FocusColumn(_) | Command::ResizeColumn(_) | Command::ToggleColumn(_)
+                if self.view != View::Table => {}
+            Command::FocusColumn(forward) => self.columns.focus(forward),
+            Command::ResizeColumn(delta) => {
+                self.columns.resize(delta);
+                return self.save_columns();
+            }
+            Command::ToggleColumn(index) => {
+                self.columns.toggle(index);
+                return self.save_columns();
+            }
             Command::None => {}
             Command::Tag => {
-                let new_ctx = TypeId::of::<TaggingContext>();
+                if let Some(file) = self.selected(state) {
+                    let new_ctx = ContextId::Tagging;
+                    return Some(
+                        Signal::Change(new_ctx)
+                            .and(Signal::Message(new_ctx, Msg::File(file.clone()))),
+                    );
+                }
+            }
+            Command::TagAll if state.loading => {
+                return Some(Signal::Status(
+                    "Still loading; wait for every entry before tagging them all".to_string(),
+                ))
+            }
+            Command::TagAll => {
+                let new_ctx = ContextId::Tagging;
                 return Some(Signal::Change(new_ctx).and(Signal::Message(
                     new_ctx,
-                    Msg::File(state.files[self.file_list_state.selected().unwrap()].clone()),
+                    Msg::Directory(PathBuf::from(&state.path)),
                 )));
             }
-            Command::Quit => return Some(Signal::Quit),
+            Command::Yank => {
+                let yanked = self.targets(state);
+                let status = match yanked.as_slice() {
+                    [] => return None,
+                    [path] => format!("Yanked {}", path.display()),
+                    paths => format!("Yanked {} entries", paths.len()),
+                };
+                self.yanked = yanked;
+                return Some(Signal::Status(status));
+            }
+            Command::Paste => {
+                let directory = Path::new(&state.path);
+                let mut plan = PastePlan::default();
+                for from in &self.yanked {
+                    if let Some(name) = from.file_name() {
+                        let to = directory.join(name);
+                        match fs::symlink_metadata(&to) {
+                            Ok(_) => plan.clashes.push((from.clone(), to)),
+                            Err(_) => plan.ready.push(CopyItem {
+                                from: from.clone(),
+                                to,
+                                overwrite: false,
+                            }),
+                        }
+                    }
+                }
+                if !self.yanked.is_empty() {
+                    return self.continue_paste(plan);
+                }
+            }
+            Command::Delete => {
+                let paths = self.targets(state);
+                if paths.is_empty() {
+                    return None;
+                }
+                if let Some(status) = protection(&*self.storage, &paths) {
+                    return Some(Signal::Status(status));
+                }
+                if self.config.confirm.asks(paths.len()) {
+                    self.pending_delete = Some(paths);
+                    return None;
+                }
+                return Some(self.delete(paths));
+            }
+            Command::Select => {
+                if let Some(file) = self.selected(state).cloned() {
+                    match self.selection.iter().position(|picked| *picked == file) {
+                        Some(index) => {
+                            self.selection.remove(index);
+                        }
+                        None => self.selection.push(file),
+                    }
+                }
+            }
+            Command::OpenWith if self.config.open_with.is_empty() => {
+                return Some(Signal::Status(
+                    "No apps to open with; list some as open_with in the config".to_string(),
+                ))
+            }
+            Command::OpenWith => {
+                if let Some(file) = self.selected(state) {
+                    let new_ctx = ContextId::OpenWith;
+                    return Some(
+                        Signal::Change(new_ctx)
+                            .and(Signal::Message(new_ctx, Msg::File(file.clone()))),
+                    );
+                }
+            }
+            Command::Open => {
+                let files = self.targets(state);
+                if !files.is_empty() {
+                    return Some(Signal::Status(open_all(&files)));
+                }
+            }
+            Command::Reindex => return Some(Signal::Reindex),
+            Command::Note => {
+                if let Some(file) = self.selected(state) {
+                    let new_ctx = ContextId::Note;
+                    return Some(
+                        Signal::Change(new_ctx)
+                            .and(Signal::Message(new_ctx, Msg::File(file.clone()))),
+                    );
+                }
+            }
+            Command::Rename => {
+                if let Some(file) = self.selected(state) {
+                    let new_ctx = ContextId::Rename;
+                    return Some(
+                        Signal::Change(new_ctx)
+                            .and(Signal::Message(new_ctx, Msg::File(file.clone()))),
+                    );
+                }
+            }
+            Command::ToggleDirsFirst => return Some(Signal::DirsFirst),
+            Command::ToggleRecursive => return Some(Signal::Recursive),
+            Command::ToggleProtected => {
+                if let Some(file) = self.selected(state) {
+                    return Some(Signal::Protect(file.clone()));
+                }
+            }
+            Command::Cancel if state.busy => return Some(Signal::Cancel),
+            Command::Cancel if self.onboarding => self.onboarding = false,
+            Command::Cancel if self.focus() != Panel::Files => self.focus = Panel::Files,
+            Command::Cancel => return Some(Signal::Cancel),
+            Command::Goto => return Some(Signal::Change(ContextId::Goto)),
+            Command::Log => return Some(Signal::Change(ContextId::Log)),
+            Command::Filter => {
+                let new_ctx = ContextId::Filter;
+                return Some(Signal::Change(new_ctx).and(Signal::Message(
+                    new_ctx,
+                    Msg::Prefill(state.filter.to_string()),
+                )));
+            }
+            Command::Search => {
+                let new_ctx = ContextId::Search;
+                return Some(Signal::Change(new_ctx).and(Signal::Message(
+                    new_ctx,
+                    Msg::Directory(PathBuf::from(&state.path)),
+                )));
+            }
+            Command::Home => match paths::home_dir() {
+                Some(home) => return Some(Signal::Navigate(home)),
+                None => return Some(Signal::Status("No home directory found.".to_string())),
+            },
+            Command::Root => {
+                if let Some(root) = Path::new(&state.path).ancestors().last() {
+                    return Some(Signal::Navigate(root.to_path_buf()));
+                }
+            }
+            Command::Pending(key) => self.pending_key = Some((key, Instant::now())),
+            Command::Copy(form) => {
+                if let Some(file) = self.selected(state) {
+                    let text = form.label(file, &self.start_dir);
+                    let kind = match form {
+                        PathDisplay::Name => "name",
+                        PathDisplay::Relative => "relative path",
+                        PathDisplay::Absolute => "path",
+                    };
+                    let status = format!("Copied {}: {}", kind, text);
+                    return Some(Signal::Clipboard(text).and(Signal::Status(status)));
+                }
+            }
+            Command::TagAgain => {
+                if let Some(file) = self.selected(state) {
+                    return Some(Signal::TagAgain(file.clone()));
+                }
+            }
+            Command::ToggleUntagged => {
+                let filter = Filter {
+                    untagged: !state.filter.untagged,
+                    ..state.filter.clone()
+                };
+                return Some(Signal::Filter(filter));
+            }
+            Command::TogglePreview => self.show_preview = !self.show_preview,
+            Command::ToggleInfo => self.show_info = !self.show_info,
+            Command::ToggleLong => self.long = !self.long,
+            Command::TypeAhead => self.type_ahead = Some((String::new(), Instant::now())),
+            Command::ToggleCommands => self.show_commands = !self.show_commands,
+            Command::Expand => {
+                self.expanded = match self.selected(state) {
+                    Some(file) if self.expanded.as_ref() != Some(file) => Some(file.clone()),
+                    _ => None,
+                };
+            }
+            Command::TogglePaths => self.path_display = self.path_display.next(),
+            Command::Edit => {
+                if let Some(file) = self.selected(state) {
+                    let editor = env::var("VISUAL")
+                        .or_else(|_| env::var("EDITOR"))
+                        .unwrap_or_else(|_| "vi".to_string());
+                    let mut words = editor.split_whitespace();
+                    let mut command = process::Command::new(words.next().unwrap_or("vi"));
+                    command.args(words).arg(file);
+                    return Some(Signal::Suspend(command));
+                }
+            }
+            Command::Reveal => {
+                if let Some(file) = self.selected(state) {
+                    if let Err(e) = reveal::reveal(file) {
+                        return Some(Signal::Status(format!(
+                            "Couldn't open a file manager: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+            Command::Custom(index) => {
+                if let (Some(file), Some(custom)) =
+                    (self.selected(state), self.config.commands.get(index))
+                {
+                    return Some(Signal::Spawn(Operation::Run(custom.command(file))));
+                }
+            }
+            Command::Shell => {
+                let shell = env::var_os("SHELL")
+                    .or_else(|| env::var_os("COMSPEC"))
+                    .unwrap_or_else(|| "sh".into());
+                let mut command = process::Command::new(shell);
+                command.current_dir(&state.path);
+                return Some(Signal::Suspend(command));
+            }
+            Command::Quit => return Some(Signal::Quit),
         };
         None
     }
 
-    fn send(&mut self, msg: Msg) {}
+    fn view(&self) -> Option<View> {
+        Some(self.view)
+    }
+
+    fn current_file(&self, di: &DirInfo) -> Option<PathBuf> {
+        self.selected(di).cloned()
+    }
+
+    fn take_signal(&mut self) -> Option<Signal> {
+        self.signal.take()
+    }
+
+    fn send(&mut self, msg: Msg) {
+        match msg {
+            Msg::Navigated => {
+                self.onboarding = false;
+                self.expanded = None;
+                self.preview = None;
+                self.preview_scroll = 0;
+                self.lookups.clear();
+                self.selection.clear();
+                self.file_list_state.select(Some(0));
+            }
+            Msg::Refreshed(len) => {
+                // The file may have changed, e.g. after editing it.
+                self.preview = None;
+                self.lookups.clear();
+                self.file_list_state
+                    .select(clamp_selection(self.file_list_state.selected(), len));
+            }
+            Msg::Select(index) => self.file_list_state.select(Some(index)),
+            Msg::Stored => self.lookups.clear(),
+            _ => {}
+        }
+    }
 }
 
+/// Suggestions only fill a line or two, so the rest are never fetched.
+const SUGGESTION_LIMIT: usize = 20;
+
 pub struct TaggingContext {
-    pub tag_input: Vec<String>,
+    pub tag_input: String,
     pub file_path: Option<PathBuf>,
+    /// Set instead of `file_path` when tagging a whole directory's contents.
+    pub directory: Option<PathBuf>,
+    pub confirm: Option<BatchConfirm>,
+    pub tags: Vec<String>,
+    pub suggestions: Vec<String>,
+    pub error: Option<String>,
+    pub storage: Rc<dyn Storage>,
+    pub config: Rc<Config>,
+}
+
+/// Files a directory tag would be applied to, awaiting confirmation.
+pub struct BatchConfirm {
+    files: Vec<PathBuf>,
+    recursive: Vec<PathBuf>,
+}
+
+impl TaggingContext {
+    pub fn new(storage: Rc<dyn Storage>, config: Rc<Config>) -> TaggingContext {
+        TaggingContext {
+            tag_input: String::new(),
+            file_path: None,
+            directory: None,
+            confirm: None,
+            tags: vec![],
+            suggestions: vec![],
+            error: None,
+            storage,
+            config,
+        }
+    }
+
+    fn load_directory(&mut self, directory: PathBuf) {
+        self.tags.clear();
+        self.suggestions = self
+            .storage
+            .sibling_tags(&directory, SUGGESTION_LIMIT)
+            .unwrap_or_default();
+        self.file_path = None;
+        self.directory = Some(directory);
+        self.confirm = None;
+        self.error = None;
+    }
+
+    fn load(&mut self, path: PathBuf) {
+        self.tags = self.storage.tags_for(&path).unwrap_or_default();
+        let siblings = path
+            .parent()
+            .and_then(|parent| self.storage.sibling_tags(parent, SUGGESTION_LIMIT).ok())
+            .unwrap_or_default();
+        self.suggestions.clear();
+        // A directory's "extension" says nothing about what's in it.
+        let by_extension = match path.is_dir() {
+            true => &[],
+            false => extension_tags(&path),
+        };
+        for tag in by_extension
+            .iter()
+            .map(|tag| tag.to_string())
+            .chain(siblings)
+        {
+            if !self.tags.contains(&tag) && !self.suggestions.contains(&tag) {
+                self.suggestions.push(tag);
+            }
+        }
+        self.file_path = Some(path);
+        self.directory = None;
+        self.confirm = None;
+        self.error = None;
+    }
+
+    fn typed_tags(&self) -> Vec<String> {
+        self.tag_input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn save(&mut self, di: &DirInfo) -> Option<Signal> {
+        if self.config.read_only {
+            self.error = Some("Read-only mode".to_string());
+            return None;
+        }
+        if self.typed_tags().is_empty() {
+            self.error = Some("Type one or more tags first".to_string());
+            return None;
+        }
+        if let Some(directory) = &self.directory {
+            self.confirm = Some(BatchConfirm {
+                files: di.files.iter().filter(|f| !f.is_dir()).cloned().collect(),
+                recursive: paths::walk(directory),
+            });
+            return None;
+        }
+        let path = self.file_path.clone()?;
+        let tags = self.typed_tags();
+        if let Err(e) = self.storage.add_tags(&path, &tags) {
+            self.error = Some(e.to_string());
+            return None;
+        }
+        self.tag_input.clear();
+        self.load(path.clone());
+        let message = format!("Tagged {} with {}", path.display(), tags.join(", "));
+        Some(
+            Signal::Change(ContextId::Main)
+                .and(Signal::Status(message.clone()))
+                .and(LogContext::record(message))
+                .and(Signal::Tagged(tags)),
+        )
+    }
+
+    /// Drops the last of the file's existing tags.
+    fn remove_last_tag(&mut self) -> Option<Signal> {
+        if self.config.read_only {
+            self.error = Some("Read-only mode".to_string());
+            return None;
+        }
+        let path = self.file_path.clone()?;
+        let tag = self.tags.last()?.clone();
+        if let Err(e) = self.storage.remove_tag(&path, &tag) {
+            self.error = Some(e.to_string());
+            return None;
+        }
+        self.load(path.clone());
+        Some(LogContext::record(format!(
+            "Removed tag {} from {}",
+            tag,
+            path.display()
+        )))
+    }
+
+    fn save_batch(&mut self, files: &[PathBuf]) -> Option<Signal> {
+        let tags = self.typed_tags();
+        if let Err(e) = self.storage.add_tags_batch(files, &tags) {
+            self.error = Some(e.to_string());
+            return None;
+        }
+        self.tag_input.clear();
+        let message = format!("Tagged {} files with {}", files.len(), tags.join(", "));
+        Some(
+            Signal::Change(ContextId::Main)
+                .and(Signal::Status(message.clone()))
+                .and(LogContext::record(message))
+                .and(Signal::Tagged(tags)),
+        )
+    }
 }
+
 impl Ctx for TaggingContext {
-    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stdout>>, di: DirInfo) {
+    fn id(&self) -> ContextId {
+        ContextId::Tagging
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
         let size = rect.size();
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .margin(2)
+            .margin(self.config.density.margin(2))
             .constraints(
                 [
                     Constraint::Length(3),
@@ -198,42 +1769,2083 @@ impl Ctx for TaggingContext {
             .split(size);
         let command_block = Block::default()
             .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White))
-            .title("Tag Screen")
+            .style(self.config.theme.fg(Color::White))
+            .title(format!(
+                "Tag Screen {}",
+                hint_line(TAGGING_KEYS.iter().copied())
+            ))
             .border_type(BorderType::Plain);
 
-        let paragraph = Paragraph::new(format!("{:?}", self.file_path));
+        let paragraph = Paragraph::new(match (&self.file_path, &self.directory) {
+            (Some(path), _) if path.is_dir() => format!("Directory {}", path.display()),
+            (Some(path), _) => path.display().to_string(),
+            (None, Some(directory)) => format!("Every file in {}", directory.display()),
+            (None, None) => String::new(),
+        });
         rect.render_widget(paragraph, chunks[0]);
-        rect.render_widget(command_block, chunks[1]);
+
+        let typed = self.typed_tags();
+        let mut lines = vec![
+            Spans::from(
+                std::iter::once(Span::raw("Tags: "))
+                    .chain(tag_pills(&self.tags, self.config.theme))
+                    .collect::<Vec<_>>(),
+            ),
+            Spans::from(format!("> {}", self.tag_input)),
+            Spans::from(""),
+        ];
+        let suggestions: Vec<Span> = self
+            .suggestions
+            .iter()
+            .filter(|tag| !typed.contains(tag))
+            .map(|tag| Span::styled(format!("{} ", tag), self.config.theme.fg(Color::LightCyan)))
+            .collect();
+        if !suggestions.is_empty() {
+            lines.push(Spans::from(
+                std::iter::once(Span::raw("Suggestions: "))
+                    .chain(suggestions)
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        if let Some(confirm) = &self.confirm {
+            lines.push(Spans::from(Span::styled(
+                format!(
+                    "Tag {} files? (y)es, (r)ecursively: {} files, (n)o",
+                    confirm.files.len(),
+                    confirm.recursive.len()
+                ),
+                self.config.theme.fg(Color::Yellow),
+            )));
+        }
+        if let Some(error) = &self.error {
+            lines.push(Spans::from(Span::styled(
+                error.clone(),
+                self.config.theme.fg(Color::Red),
+            )));
+        }
+        rect.render_widget(Paragraph::new(lines).block(command_block), chunks[1]);
     }
 
-    fn handle_key(&mut self, key: KeyEvent, di: DirInfo) -> Option<Signal> {
-        match key {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                ..
-            } => Some(Signal::Change(TypeId::of::<MainContext>())),
-            _ => None,
+    fn handle_key(&mut self, key: KeyEvent, di: &DirInfo) -> Option<Signal> {
+        if let Some(confirm) = self.confirm.take() {
+            return match key.code {
+                KeyCode::Char('y') => self.save_batch(&confirm.files),
+                KeyCode::Char('r') => self.save_batch(&confirm.recursive),
+                _ => None,
+            };
+        }
+        match key.code {
+            // Keeps what was typed, in case it's the same target next time.
+            KeyCode::Esc => return Some(Signal::Change(ContextId::Main)),
+            KeyCode::Enter => return self.save(di),
+            KeyCode::Tab => {
+                let typed = self.typed_tags();
+                if let Some(tag) = self.suggestions.iter().find(|tag| !typed.contains(tag)) {
+                    if !self.tag_input.is_empty() && !self.tag_input.ends_with([' ', ','].as_ref())
+                    {
+                        self.tag_input.push(' ');
+                    }
+                    self.tag_input.push_str(tag);
+                }
+            }
+            KeyCode::Backspace if self.tag_input.is_empty() => return self.remove_last_tag(),
+            KeyCode::Backspace => {
+                self.tag_input.pop();
+            }
+            KeyCode::Char(c) => self.tag_input.push(c),
+            _ => {}
         }
+        None
     }
     fn send(&mut self, msg: Msg) {
         match msg {
-            Msg::File(path) => self.file_path = Some(path),
+            // Typed tags survive leaving and coming back to the same target.
+            Msg::File(path) => {
+                if self.file_path.as_ref() != Some(&path) {
+                    self.tag_input.clear();
+                }
+                self.load(path)
+            }
+            Msg::Directory(directory) => {
+                if self.directory.as_ref() != Some(&directory) {
+                    self.tag_input.clear();
+                }
+                self.load_directory(directory)
+            }
+            _ => {}
         }
     }
 }
 
-fn metadata_str(metadata: Metadata) -> String {
-    let formatter = |date: SystemTime| {
-        DateTime::<Utc>::from(date)
-            .format("%a %b %e %T %Y")
-            .to_string()
-    };
-    let created = metadata.created().map(formatter).unwrap();
-    let accessed = metadata.accessed().map(formatter).unwrap();
-    let modified = metadata.modified().map(formatter).unwrap();
-    format!(
-        "Created: {}, Accessed: {}, Modified: {}",
-        created, accessed, modified
-    )
+/// Tags suggested for a file based on its extension alone.
+fn extension_tags(path: &Path) -> &'static [&'static str] {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "webp") => &["image"],
+        Some("mp3" | "flac" | "wav" | "ogg" | "m4a") => &["audio"],
+        Some("mp4" | "mkv" | "mov" | "avi" | "webm") => &["video"],
+        Some("pdf" | "doc" | "docx" | "odt" | "txt" | "md") => &["document"],
+        Some("zip" | "tar" | "gz" | "xz" | "7z" | "rar") => &["archive"],
+        Some("rs" | "py" | "js" | "ts" | "c" | "h" | "cpp" | "go" | "java") => &["code"],
+        _ => &[],
+    }
+}
+
+/// Prompt for jumping straight to a typed path.
+pub struct GotoContext {
+    pub input: String,
+    pub candidates: Vec<String>,
+    pub error: Option<String>,
+    pub config: Rc<Config>,
+}
+impl Ctx for GotoContext {
+    fn id(&self) -> ContextId {
+        ContextId::Goto
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
+            .split(size);
+        let input = Paragraph::new(self.input.clone()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .title(format!("Go to {}", hint_line(GOTO_KEYS.iter().copied())))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(input, chunks[0]);
+
+        let items: Vec<_> = match &self.error {
+            Some(error) => vec![ListItem::new(Span::styled(
+                error.clone(),
+                self.config.theme.fg(Color::Red),
+            ))],
+            None => self
+                .candidates
+                .iter()
+                .map(|name| ListItem::new(name.clone()))
+                .collect(),
+        };
+        let list = List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(list, chunks[1]);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        match key.code {
+            KeyCode::Esc => {
+                self.input.clear();
+                self.candidates.clear();
+                self.error = None;
+                return Some(Signal::Change(ContextId::Main));
+            }
+            KeyCode::Enter => match paths::check_dir(&paths::expand_tilde(&self.input)) {
+                Ok(path) => {
+                    self.input.clear();
+                    self.candidates.clear();
+                    self.error = None;
+                    return Some(Signal::Change(ContextId::Main).and(Signal::Navigate(path)));
+                }
+                Err(e) => self.error = Some(format!("{}: {}", self.input, e)),
+            },
+            KeyCode::Tab => {
+                let (input, candidates) = paths::complete(&self.input);
+                self.input = input;
+                self.candidates = candidates;
+                self.error = None;
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.error = None;
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.error = None;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, _msg: Msg) {}
+}
+
+/// Prompt for writing a free-text note on an entry.
+pub struct NoteContext {
+    pub input: String,
+    pub file_path: Option<PathBuf>,
+    pub error: Option<String>,
+    pub storage: Rc<dyn Storage>,
+    pub config: Rc<Config>,
+}
+
+impl NoteContext {
+    pub fn new(storage: Rc<dyn Storage>, config: Rc<Config>) -> NoteContext {
+        NoteContext {
+            input: String::new(),
+            file_path: None,
+            error: None,
+            storage,
+            config,
+        }
+    }
+
+    fn save(&mut self) -> Option<Signal> {
+        if self.config.read_only {
+            self.error = Some("Read-only mode".to_string());
+            return None;
+        }
+        let path = self.file_path.clone()?;
+        let note = self.input.trim();
+        if let Err(e) = self.storage.set_note(&path, note) {
+            self.error = Some(e.to_string());
+            return None;
+        }
+        let message = match note.is_empty() {
+            true => format!("Removed the note on {}", path.display()),
+            false => format!("Noted on {}: {}", path.display(), note),
+        };
+        Some(
+            Signal::Change(ContextId::Main)
+                .and(Signal::Message(ContextId::Main, Msg::Stored))
+                .and(Signal::Status(message.clone()))
+                .and(LogContext::record(message)),
+        )
+    }
+}
+
+impl Ctx for NoteContext {
+    fn id(&self) -> ContextId {
+        ContextId::Note
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
+            .split(size);
+        let path = self
+            .file_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        rect.render_widget(Paragraph::new(path), chunks[0]);
+
+        let mut lines = vec![Spans::from(format!("> {}", self.input))];
+        if let Some(error) = &self.error {
+            lines.push(Spans::from(Span::styled(
+                error.clone(),
+                self.config.theme.fg(Color::Red),
+            )));
+        }
+        let note = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .title(format!("Note {}", hint_line(NOTE_KEYS.iter().copied())))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(note, chunks[1]);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        match key.code {
+            KeyCode::Esc => {
+                self.error = None;
+                return Some(Signal::Change(ContextId::Main));
+            }
+            KeyCode::Enter => return self.save(),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::File(path) = msg {
+            self.input = self.storage.note(&path).ok().flatten().unwrap_or_default();
+            self.file_path = Some(path);
+            self.error = None;
+        }
+    }
+}
+
+/// Prompt for a new name for an entry, which stays in its directory.
+pub struct RenameContext {
+    pub input: String,
+    pub file_path: Option<PathBuf>,
+    pub error: Option<String>,
+    pub storage: Rc<dyn Storage>,
+    pub config: Rc<Config>,
+}
+
+impl RenameContext {
+    pub fn new(storage: Rc<dyn Storage>, config: Rc<Config>) -> RenameContext {
+        RenameContext {
+            input: String::new(),
+            file_path: None,
+            error: None,
+            storage,
+            config,
+        }
+    }
+
+    fn rename(&mut self) -> Option<Signal> {
+        if self.config.read_only {
+            self.error = Some("Read-only mode".to_string());
+            return None;
+        }
+        let from = self.file_path.clone()?;
+        if let Err(e) = paths::check_name(&self.input) {
+            self.error = Some(e);
+            return None;
+        }
+        let to = from.with_file_name(&self.input);
+        if to == from {
+            return Some(Signal::Change(ContextId::Main));
+        }
+        if let Some(status) = protection(&*self.storage, std::slice::from_ref(&from)) {
+            self.error = Some(status);
+            return None;
+        }
+        if let Err(e) = paths::rename(&from, &to) {
+            self.error = Some(match e.kind() {
+                io::ErrorKind::AlreadyExists => format!("{} already exists", self.input),
+                _ => e.to_string(),
+            });
+            return None;
+        }
+        let mut message = format!("Renamed {} to {}", from.display(), self.input);
+        if let Err(e) = self.storage.move_path(&from, &to) {
+            message = format!("{}, but its tags were left behind: {}", message, e);
+        }
+        Some(
+            Signal::Change(ContextId::Main)
+                .and(Signal::Status(message.clone()))
+                .and(LogContext::record(message))
+                .and(Signal::Refresh)
+                .and(Signal::Reveal(to)),
+        )
+    }
+}
+
+impl Ctx for RenameContext {
+    fn id(&self) -> ContextId {
+        ContextId::Rename
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
+            .split(size);
+        let path = self
+            .file_path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        rect.render_widget(Paragraph::new(path), chunks[0]);
+
+        let mut lines = vec![Spans::from(format!("> {}", self.input))];
+        if let Some(error) = &self.error {
+            lines.push(Spans::from(Span::styled(
+                error.clone(),
+                self.config.theme.fg(Color::Red),
+            )));
+        }
+        let rename = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .title(format!("Rename {}", hint_line(RENAME_KEYS.iter().copied())))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(rename, chunks[1]);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        match key.code {
+            KeyCode::Esc => {
+                self.error = None;
+                return Some(Signal::Change(ContextId::Main));
+            }
+            KeyCode::Enter => return self.rename(),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+        // Checked as it's typed, so a bad name shows before Enter.
+        self.error = paths::check_name(&self.input).err();
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::File(path) = msg {
+            self.input = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            self.file_path = Some(path);
+            self.error = None;
+        }
+    }
+}
+
+/// Prompt for narrowing the listing, e.g. to files older than six months.
+pub struct FilterContext {
+    pub input: String,
+    pub error: Option<String>,
+    pub config: Rc<Config>,
+}
+
+impl FilterContext {
+    pub fn new(config: Rc<Config>) -> FilterContext {
+        FilterContext {
+            input: String::new(),
+            error: None,
+            config,
+        }
+    }
+}
+
+impl Ctx for FilterContext {
+    fn id(&self) -> ContextId {
+        ContextId::Filter
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
+            .split(size);
+        let input = Paragraph::new(self.input.clone()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .title(format!("Filter {}", hint_line(FILTER_KEYS.iter().copied())))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(input, chunks[0]);
+
+        let help = match &self.error {
+            Some(error) => Spans::from(Span::styled(
+                error.clone(),
+                self.config.theme.fg(Color::Red),
+            )),
+            None => Spans::from(
+                "age>6mo: not modified in 6 months, age<1d: modified today, size>100M: files over 100MiB",
+            ),
+        };
+        let help = Paragraph::new(help).wrap(Wrap { trim: true }).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(help, chunks[1]);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        match key.code {
+            KeyCode::Esc => {
+                self.error = None;
+                return Some(Signal::Change(ContextId::Main));
+            }
+            KeyCode::Enter => match Filter::parse(&self.input) {
+                Ok(filter) => {
+                    self.error = None;
+                    return Some(Signal::Change(ContextId::Main).and(Signal::Filter(filter)));
+                }
+                Err(e) => self.error = Some(e),
+            },
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.error = None;
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.error = None;
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::Prefill(input) = msg {
+            self.input = input;
+        }
+    }
+}
+
+/// Lists what went wrong in an operation that partly failed, until dismissed.
+pub struct SummaryContext {
+    summary: Summary,
+    list_state: ListState,
+    config: Rc<Config>,
+}
+
+impl SummaryContext {
+    pub fn new(config: Rc<Config>) -> SummaryContext {
+        SummaryContext {
+            summary: Summary::default(),
+            list_state: ListState::default(),
+            config,
+        }
+    }
+}
+
+impl Ctx for SummaryContext {
+    fn id(&self) -> ContextId {
+        ContextId::Summary
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Min(5)].as_ref())
+            .split(size);
+        let items: Vec<_> = self
+            .summary
+            .failed
+            .iter()
+            .map(|(path, error)| {
+                ListItem::new(Spans::from(vec![
+                    Span::raw(format!("{} ", path.display())),
+                    Span::styled(error.clone(), self.config.theme.fg(Color::Red)),
+                ]))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(self.config.theme.fg(Color::White))
+                    .title(format!("{} {}", self.summary.headline(), list_hints()))
+                    .border_type(BorderType::Plain),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+        rect.render_stateful_widget(list, chunks[0], &mut self.list_state);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        let len = self.summary.failed.len();
+        let selected = self.list_state.selected();
+        match lookup(LIST_KEYS, key.code) {
+            Command::Cancel => return Some(Signal::Change(ContextId::Main)),
+            Command::CursorUp => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_up(selected, len, false));
+                }
+            }
+            Command::CursorDown => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_down(selected, len, false));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::Summary(summary) = msg {
+            self.summary = summary;
+            self.list_state.select(Some(0));
+        }
+    }
+}
+
+/// What was done this session, oldest first.
+pub struct LogContext {
+    entries: Vec<(DateTime<Local>, String)>,
+    list_state: ListState,
+    config: Rc<Config>,
+}
+
+impl LogContext {
+    pub fn new(config: Rc<Config>) -> LogContext {
+        LogContext {
+            entries: vec![],
+            list_state: ListState::default(),
+            config,
+        }
+    }
+
+    /// A signal that adds `message` to the log.
+    pub fn record(message: String) -> Signal {
+        Signal::Message(ContextId::Log, Msg::Log(message))
+    }
+}
+
+impl Ctx for LogContext {
+    fn id(&self) -> ContextId {
+        ContextId::Log
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Min(5)].as_ref())
+            .split(size);
+        let items: Vec<_> = match self.entries.is_empty() {
+            true => vec![ListItem::new("Nothing done yet.")],
+            false => self
+                .entries
+                .iter()
+                .map(|(time, message)| {
+                    ListItem::new(Spans::from(vec![
+                        Span::styled(
+                            format!("{} ", time.format("%T")),
+                            self.config.theme.fg(Color::DarkGray),
+                        ),
+                        Span::raw(message.clone()),
+                    ]))
+                })
+                .collect(),
+        };
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(self.config.theme.fg(Color::White))
+                    .title(format!("Log {}", list_hints()))
+                    .border_type(BorderType::Plain),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::BOLD));
+        rect.render_stateful_widget(list, chunks[0], &mut self.list_state);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        let len = self.entries.len();
+        let selected = self.list_state.selected();
+        match lookup(LIST_KEYS, key.code) {
+            Command::Cancel => return Some(Signal::Change(ContextId::Main)),
+            Command::CursorUp => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_up(selected, len, false));
+                }
+            }
+            Command::CursorDown => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_down(selected, len, false));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::Log(message) = msg {
+            self.entries.push((Local::now(), message));
+            self.list_state.select(Some(self.entries.len() - 1));
+        }
+    }
+}
+
+/// Menu of the apps from `open_with` in the config, for opening a file with
+/// something other than its default app.
+pub struct OpenWithContext {
+    file: Option<PathBuf>,
+    list_state: ListState,
+    config: Rc<Config>,
+}
+
+impl OpenWithContext {
+    pub fn new(config: Rc<Config>) -> OpenWithContext {
+        OpenWithContext {
+            file: None,
+            list_state: ListState::default(),
+            config,
+        }
+    }
+
+    fn open(&self) -> Option<Signal> {
+        let file = self.file.as_ref()?;
+        let app = self.config.open_with.get(self.list_state.selected()?)?;
+        let status = match reveal::open_with(app, file) {
+            Ok(()) => format!("Opened {} with {}", file.display(), app),
+            Err(e) => format!("Couldn't open {} with {}: {}", file.display(), app, e),
+        };
+        Some(Signal::Change(ContextId::Main).and(Signal::Status(status)))
+    }
+}
+
+impl Ctx for OpenWithContext {
+    fn id(&self) -> ContextId {
+        ContextId::OpenWith
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Min(5)].as_ref())
+            .split(size);
+        let items: Vec<_> = self
+            .config
+            .open_with
+            .iter()
+            .map(|app| ListItem::new(app.as_str()))
+            .collect();
+        let name = self
+            .file
+            .as_ref()
+            .and_then(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(self.config.theme.fg(Color::White))
+                    .title(format!("Open {} with (Enter) open {}", name, list_hints()))
+                    .border_type(BorderType::Plain),
+            )
+            .highlight_style(self.config.theme.highlight());
+        rect.render_stateful_widget(list, chunks[0], &mut self.list_state);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        if key.code == KeyCode::Enter {
+            return self.open();
+        }
+        let len = self.config.open_with.len();
+        let selected = self.list_state.selected();
+        match lookup(LIST_KEYS, key.code) {
+            Command::Cancel => return Some(Signal::Change(ContextId::Main)),
+            Command::CursorUp => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_up(selected, len, true));
+                }
+            }
+            Command::CursorDown => {
+                if let Some(selected) = selected {
+                    self.list_state.select(cursor_down(selected, len, true));
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::File(file) = msg {
+            self.file = Some(file);
+            let first = Some(0).filter(|_| !self.config.open_with.is_empty());
+            self.list_state.select(first);
+        }
+    }
+}
+
+/// Finds files by name anywhere below the directory it was opened in.
+pub struct SearchContext {
+    pub input: String,
+    root: PathBuf,
+    files: Vec<PathBuf>,
+    /// Files found by the walk still going on in the background.
+    incoming: Option<mpsc::Receiver<Vec<PathBuf>>>,
+    results: Vec<PathBuf>,
+    list_state: ListState,
+    history: History,
+    /// Last result shown in the main view while staying in the results.
+    revealed: Option<PathBuf>,
+    config: Rc<Config>,
+}
+
+impl SearchContext {
+    pub fn new(config: Rc<Config>) -> SearchContext {
+        SearchContext {
+            input: String::new(),
+            root: PathBuf::new(),
+            files: vec![],
+            incoming: None,
+            results: vec![],
+            list_state: ListState::default(),
+            history: History::default(),
+            revealed: None,
+            config,
+        }
+    }
+
+    fn update_results(&mut self) {
+        let query = &self.input;
+        self.results = self
+            .files
+            .iter()
+            .filter(|file| {
+                file.file_name()
+                    .map(|name| {
+                        query.is_empty()
+                            || find_ignore_case(&name.to_string_lossy(), query).is_some()
+                    })
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect();
+        self.list_state.select(clamp_selection(
+            self.list_state.selected(),
+            self.results.len(),
+        ));
+    }
+
+    /// Opens the selected result's directory in the main view with the
+    /// result highlighted.
+    fn reveal(&self) -> Option<Signal> {
+        let file = self
+            .list_state
+            .selected()
+            .and_then(|i| self.results.get(i))?;
+        let directory = file.parent()?.to_path_buf();
+        Some(Signal::Navigate(directory).and(Signal::Reveal(file.clone())))
+    }
+
+    /// Walks `directory` on another thread, so a big tree doesn't hold up
+    /// the UI. Results are taken in by `receive`.
+    fn search(&mut self, directory: PathBuf) {
+        let (sender, receiver) = mpsc::channel();
+        let max_depth = self.config.max_depth;
+        let root = directory.clone();
+        thread::spawn(move || {
+            let mut batch = Vec::with_capacity(SEARCH_BATCH);
+            let mut count = 0;
+            paths::walk_each(&root, max_depth, &mut |file| {
+                batch.push(file);
+                count += 1;
+                if batch.len() == SEARCH_BATCH {
+                    // Closing the search drops the receiver, ending the walk.
+                    if sender.send(std::mem::take(&mut batch)).is_err() {
+                        return false;
+                    }
+                }
+                count < SEARCH_LIMIT
+            });
+            let _ = sender.send(batch);
+        });
+        self.files.clear();
+        self.incoming = Some(receiver);
+        self.root = directory;
+        self.list_state.select(None);
+        self.update_results();
+    }
+
+    /// Takes in what the walk found since the last frame.
+    fn receive(&mut self) {
+        let receiver = match &self.incoming {
+            Some(receiver) => receiver,
+            None => return,
+        };
+        let mut received = false;
+        loop {
+            match receiver.try_recv() {
+                Ok(batch) => {
+                    self.files.extend(batch);
+                    received = true;
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.incoming = None;
+                    break;
+                }
+            }
+        }
+        if received {
+            self.files.sort();
+            self.update_results();
+        }
+    }
+
+    fn close(&mut self) {
+        self.input.clear();
+        self.incoming = None;
+        self.files.clear();
+        self.results.clear();
+        self.revealed = None;
+        self.history.reset();
+    }
+}
+
+/// Most recent queries the user submitted this session, oldest first.
+#[derive(Default)]
+struct History {
+    entries: Vec<String>,
+    /// Which entry is being shown while stepping through with Up/Down.
+    position: Option<usize>,
+}
+
+impl History {
+    const LIMIT: usize = 50;
+
+    fn push(&mut self, query: &str) {
+        self.reset();
+        if query.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != query);
+        self.entries.push(query.to_string());
+        if self.entries.len() > History::LIMIT {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Steps back to an older query, staying on the oldest.
+    fn older(&mut self) -> Option<&str> {
+        let position = match self.position {
+            Some(position) => position.saturating_sub(1),
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.position = Some(position);
+        self.entries.get(position).map(String::as_str)
+    }
+
+    /// Steps forward to a newer query, or past the newest back to an empty
+    /// input.
+    fn newer(&mut self) -> Option<&str> {
+        let position = self.position? + 1;
+        self.position = Some(position).filter(|&position| position < self.entries.len());
+        match self.position {
+            Some(position) => self.entries.get(position).map(String::as_str),
+            None => Some(""),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.position = None;
+    }
+}
+
+impl Ctx for SearchContext {
+    fn id(&self) -> ContextId {
+        ContextId::Search
+    }
+
+    fn render(&mut self, rect: &mut tui::Frame<CrosstermBackend<io::Stderr>>, _di: &DirInfo) {
+        let size = rect.size();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(self.config.density.margin(2))
+            .constraints([Constraint::Length(3), Constraint::Min(5)].as_ref())
+            .split(size);
+        self.receive();
+        let input = Paragraph::new(self.input.clone()).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .style(self.config.theme.fg(Color::White))
+                .title(format!("Search {}", hint_line(SEARCH_KEYS.iter().copied())))
+                .border_type(BorderType::Plain),
+        );
+        rect.render_widget(input, chunks[0]);
+
+        let items: Vec<_> = self
+            .results
+            .iter()
+            .map(|file| {
+                let name = file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let mut spans = vec![Span::raw("📄")];
+                match find_ignore_case(&name, &self.input) {
+                    Some(found) => {
+                        spans.push(Span::raw(name[..found.start].to_string()));
+                        spans.push(Span::styled(
+                            name[found.clone()].to_string(),
+                            self.config.theme.accent(Color::LightGreen),
+                        ));
+                        spans.push(Span::raw(name[found.end..].to_string()));
+                    }
+                    None => spans.push(Span::raw(name.clone())),
+                }
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    result_location(file, &self.root),
+                    self.config.theme.fg(Color::DarkGray),
+                ));
+                ListItem::new(Spans::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .style(self.config.theme.fg(Color::White))
+                    .title(match &self.revealed {
+                        Some(file) => format!(
+                            "Results ({}) in {}, showing {} (Esc to see it)",
+                            self.results.len(),
+                            self.root.display(),
+                            file.strip_prefix(&self.root).unwrap_or(file).display()
+                        ),
+                        None => format!(
+                            "Results ({}) in {}{}",
+                            self.results.len(),
+                            self.root.display(),
+                            match (&self.incoming, self.files.len()) {
+                                (Some(_), _) => ", searching…".to_string(),
+                                (None, SEARCH_LIMIT) => {
+                                    format!(", first {} files only", SEARCH_LIMIT)
+                                }
+                                (None, _) => String::new(),
+                            }
+                        ),
+                    })
+                    .border_type(BorderType::Plain),
+            )
+            .highlight_style(self.config.theme.highlight());
+        rect.render_stateful_widget(list, chunks[1], &mut self.list_state);
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, _di: &DirInfo) -> Option<Signal> {
+        match key.code {
+            KeyCode::Esc => {
+                self.close();
+                return Some(Signal::Change(ContextId::Main));
+            }
+            KeyCode::Enter => {
+                self.history.push(&self.input);
+                let reveal = self.reveal()?;
+                self.close();
+                return Some(Signal::Change(ContextId::Main).and(reveal));
+            }
+            KeyCode::Right => {
+                let reveal = self.reveal()?;
+                self.revealed = self
+                    .list_state
+                    .selected()
+                    .and_then(|i| self.results.get(i))
+                    .cloned();
+                return Some(reveal);
+            }
+            KeyCode::Up | KeyCode::Down => {
+                let query = match key.code {
+                    KeyCode::Up => self.history.older(),
+                    _ => self.history.newer(),
+                };
+                if let Some(query) = query {
+                    self.input = query.to_string();
+                    self.list_state.select(None);
+                    self.update_results();
+                }
+            }
+            KeyCode::BackTab => {
+                if let Some(selected) = self.list_state.selected() {
+                    self.list_state.select(cursor_up(
+                        selected,
+                        self.results.len(),
+                        self.config.wrap_cursor,
+                    ));
+                }
+            }
+            KeyCode::Tab => {
+                if let Some(selected) = self.list_state.selected() {
+                    self.list_state.select(cursor_down(
+                        selected,
+                        self.results.len(),
+                        self.config.wrap_cursor,
+                    ));
+                }
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.history.reset();
+                self.update_results();
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                self.history.reset();
+                self.update_results();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn send(&mut self, msg: Msg) {
+        if let Msg::Directory(directory) = msg {
+            self.search(directory);
+        }
+    }
+}
+
+/// How many files search looks at, at most, since the walk follows
+/// `max_depth` but a wide tree can still be huge.
+const SEARCH_LIMIT: usize = 100_000;
+
+/// Files sent from the search walk at a time.
+const SEARCH_BATCH: usize = 1000;
+
+/// The byte range of the first case-insensitive occurrence of `needle`.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    if needle.is_empty() {
+        return None;
+    }
+    haystack.char_indices().find_map(|(start, _)| {
+        let mut chars = haystack[start..].char_indices();
+        for n in needle.chars() {
+            let (_, c) = chars.next()?;
+            if !c.to_lowercase().eq(n.to_lowercase()) {
+                return None;
+            }
+        }
+        let end = chars.next().map_or(haystack.len(), |(i, _)| start + i);
+        Some(start..end)
+    })
+}
+
+/// Where a search result lives, relative to the directory searched from.
+fn result_location(file: &Path, root: &Path) -> String {
+    let parent = file.parent().unwrap_or(root);
+    match parent.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => format!(".{}{}", std::path::MAIN_SEPARATOR, relative.display()),
+        Err(_) => parent.display().to_string(),
+    }
+}
+
+/// One of the `info` fields of an entry. The hash is worked out elsewhere,
+/// as it reads the whole file, so it's passed in once it's known.
+fn field_str(field: InfoField, metadata: &Metadata, config: &Config, hash: Option<&str>) -> String {
+    let formatter = |date: io::Result<SystemTime>| match date.ok().and_then(local_time) {
+        Some(date) => date.format(&config.time_format).to_string(),
+        None => "-".to_string(),
+    };
+    match field {
+        InfoField::Type => format!("Type: {}", file_type(metadata)),
+        InfoField::Size => format!("Size: {}", human_size(metadata.len())),
+        InfoField::Permissions => format!("Permissions: {}", permissions(metadata)),
+        InfoField::Owner => format!("Owner: {}", owner(metadata)),
+        InfoField::Created => format!("Created: {}", formatter(metadata.created())),
+        InfoField::Accessed => format!("Accessed: {}", formatter(metadata.accessed())),
+        InfoField::Modified => format!("Modified: {}", formatter(metadata.modified())),
+        InfoField::Hash if metadata.is_dir() => "Hash: -".to_string(),
+        InfoField::Hash => format!("Hash: {}", hash.unwrap_or("hashing…")),
+        InfoField::Tags => "Tags".to_string(),
+    }
+}
+
+/// The `fields` in their configured order. Fields next to each other share
+/// a line, and the tags get one of their own wherever they're listed.
+fn info_lines(
+    fields: &[InfoField],
+    field_str: impl Fn(InfoField) -> String,
+    tags: Option<Spans<'static>>,
+    seen: Option<String>,
+) -> Vec<Spans<'static>> {
+    let mut lines = vec![];
+    let mut run = vec![];
+    for &field in fields {
+        match field {
+            InfoField::Tags => {
+                if !run.is_empty() {
+                    lines.push(Spans::from(run.join(", ")));
+                    run.clear();
+                }
+                lines.extend(tags.clone());
+            }
+            field => run.push(field_str(field)),
+        }
+    }
+    run.extend(seen);
+    if !run.is_empty() {
+        lines.push(Spans::from(run.join(", ")));
+    }
+    lines
+}
+
+/// Why `paths` can't be deleted or replaced, if any of them is or holds a
+/// protected entry. Refuses when unsure rather than risk a protected file.
+fn protection(storage: &dyn Storage, paths: &[PathBuf]) -> Option<String> {
+    paths
+        .iter()
+        .find_map(|path| match storage.protected_in(path) {
+            Ok(protected) => protected
+                .first()
+                .map(|protected| format!("{} is protected", protected.display())),
+            Err(e) => Some(format!("Couldn't check for protected files: {}", e)),
+        })
+}
+
+/// Converts a file timestamp to the local timezone, or `None` if it's out of
+/// chrono's range. A fixed instant always maps to exactly one local time.
+fn local_time(date: SystemTime) -> Option<DateTime<Local>> {
+    let (secs, nanos) = match date.duration_since(UNIX_EPOCH) {
+        Ok(since) => (since.as_secs() as i64, since.subsec_nanos()),
+        Err(e) => match e.duration() {
+            before if before.subsec_nanos() == 0 => (-(before.as_secs() as i64), 0),
+            before => (
+                -(before.as_secs() as i64) - 1,
+                1_000_000_000 - before.subsec_nanos(),
+            ),
+        },
+    };
+    Local.timestamp_opt(secs, nanos).single()
+}
+
+/// Opens each of `files` with its default application, describing any that
+/// couldn't be.
+fn open_all(files: &[PathBuf]) -> String {
+    let failed: Vec<_> = files
+        .iter()
+        .filter_map(|file| {
+            reveal::open(file)
+                .err()
+                .map(|e| format!("{}: {}", file.display(), e))
+        })
+        .collect();
+    match failed.len() {
+        0 => format!("Opened {} files", files.len()),
+        n => format!(
+            "Opened {} files, {} failed: {}",
+            files.len() - n,
+            n,
+            failed.join("; ")
+        ),
+    }
+}
+
+/// How long ago something happened, in the largest whole unit.
+fn ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86399 => format!("{} h ago", secs / 3600),
+        86400..=172799 => "1 day ago".to_string(),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+/// Shortens `name` to `width` characters by replacing its middle with an
+/// ellipsis, so both the start and the extension stay readable.
+fn truncate_middle(name: &str, width: usize) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= width {
+        return name.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let tail = (width - 1) / 2;
+    let head = width - 1 - tail;
+    chars[..head]
+        .iter()
+        .chain(&['…'])
+        .chain(&chars[chars.len() - tail..])
+        .collect()
+}
+
+/// Tags as coloured pills, each keeping the same colour wherever it's shown.
+fn tag_pills(tags: &[String], theme: Theme) -> Vec<Span<'static>> {
+    tags.iter()
+        .flat_map(|tag| {
+            let pill = match theme.color {
+                true => format!(" {} ", tag),
+                false => format!("[{}]", tag),
+            };
+            vec![
+                Span::styled(pill, theme.pill(tag_color(tag))),
+                Span::raw(" "),
+            ]
+        })
+        .collect()
+}
+
+const TAG_COLORS: [Color; 12] = [
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightRed,
+    Color::LightGreen,
+    Color::LightYellow,
+    Color::LightBlue,
+    Color::LightMagenta,
+    Color::LightCyan,
+];
+
+/// Picks a colour from the tag's name alone (FNV-1a), so it's stable across
+/// runs and machines.
+fn tag_color(tag: &str) -> Color {
+    let hash = tag.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    TAG_COLORS[hash as usize % TAG_COLORS.len()]
+}
+
+/// Describes where `file` points if it's a symlink.
+fn link_str(file: &Path) -> Option<String> {
+    let target = fs::read_link(file).ok()?;
+    Some(match fs::canonicalize(file) {
+        Ok(resolved) => format!("Link: {} -> {}", target.display(), resolved.display()),
+        Err(e) => format!("Link: {} -> missing ({})", target.display(), e),
+    })
+}
+
+/// Longest line the preview reads before giving up on finding a newline.
+const PREVIEW_LINE_BYTES: u64 = 512;
+
+/// Up to `lines` lines from the start of `file`, reading no more than that
+/// many lines' worth of bytes.
+fn preview_str(file: &Path, lines: usize) -> String {
+    if file.is_dir() {
+        return "(directory)".to_string();
+    }
+    let mut bytes = vec![];
+    let read = fs::File::open(file).and_then(|file| {
+        file.take(lines as u64 * PREVIEW_LINE_BYTES)
+            .read_to_end(&mut bytes)
+    });
+    if let Err(e) = read {
+        return format!("({})", e);
+    }
+    if bytes.contains(&0) {
+        return "(binary)".to_string();
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .take(lines)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A note saying `file` is over `limit` bytes and so won't be read.
+fn too_large(file: &Path, limit: u64) -> Option<String> {
+    let size = fs::metadata(file).ok()?.len();
+    (size > limit).then(|| {
+        format!(
+            "(too large to read: {} over {})",
+            human_size(size),
+            human_size(limit)
+        )
+    })
+}
+
+/// A 64-bit FNV-1a hash of a file's contents, to tell copies apart at a
+/// glance. Not meant to guard against tampering. `None` once cancelled.
+fn hash_str(file: &Path, limit: u64, cancel: &AtomicBool) -> Option<String> {
+    if fs::metadata(file).is_ok_and(|metadata| metadata.is_dir()) {
+        return Some("-".to_string());
+    }
+    if let Some(note) = too_large(file, limit) {
+        return Some(note);
+    }
+    let mut reader = match fs::File::open(file) {
+        Ok(file) => io::BufReader::new(file),
+        Err(e) => return Some(format!("({})", e)),
+    };
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut buffer = [0; 8192];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        match reader.read(&mut buffer) {
+            Ok(0) => return Some(format!("{:016x}", hash)),
+            Ok(read) => {
+                for &byte in &buffer[..read] {
+                    hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Some(format!("({})", e)),
+        }
+    }
+}
+
+/// Format and dimensions of an image, read from its header alone.
+fn image_str(file: &Path) -> Option<String> {
+    if extension_tags(file) != ["image"] {
+        return None;
+    }
+    let mut reader = io::BufReader::new(fs::File::open(file).ok()?);
+    let format = imagesize::reader_type(&mut reader).ok()?;
+    let size = format.reader_size(&mut reader).ok()?;
+    Some(format!(
+        "Image: {:?} {}×{}",
+        format, size.width, size.height
+    ))
+}
+
+fn file_type(metadata: &Metadata) -> &'static str {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else {
+        "file"
+    }
+}
+
+/// How long the second key of a sequence like `g/` or `dd` is waited for.
+const PENDING_KEY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the keys `first` then `second` do together. `None` for sequences
+/// that mean nothing, after which `second` is handled on its own.
+fn key_sequence(first: char, second: KeyCode) -> Option<Command> {
+    match (first, second) {
+        ('g', KeyCode::Char('/')) => Some(Command::Root),
+        ('c', KeyCode::Char('n')) => Some(Command::Copy(PathDisplay::Name)),
+        ('c', KeyCode::Char('p')) => Some(Command::Copy(PathDisplay::Absolute)),
+        ('c', KeyCode::Char('r')) => Some(Command::Copy(PathDisplay::Relative)),
+        // A copy that's not finished is dropped rather than doing something.
+        ('c', _) => Some(Command::None),
+        ('d', KeyCode::Char('d')) => Some(Command::Delete),
+        ('y', KeyCode::Char('y')) => Some(Command::Yank),
+        _ => None,
+    }
+}
+
+/// How long type-ahead waits for the next character.
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Adds `c` to what's been `typed` and finds the entry from `selected` on
+/// whose name starts with it, ignoring case. Typing one letter repeatedly
+/// cycles through the entries starting with it instead, when no name starts
+/// with the repeated letters.
+fn type_ahead(
+    files: &[PathBuf],
+    selected: usize,
+    mut typed: String,
+    c: char,
+) -> (String, Option<usize>) {
+    let starts_with = |index: usize, prefix: &str| {
+        files[index]
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .is_some_and(|name| name.starts_with(&prefix.to_lowercase()))
+    };
+    let find = |from: usize, prefix: &str| {
+        (0..files.len())
+            .map(|offset| (from + offset) % files.len())
+            .find(|&index| starts_with(index, prefix))
+    };
+    typed.push(c);
+    if let Some(index) = find(selected, &typed) {
+        return (typed, Some(index));
+    }
+    if typed.chars().all(|typed| typed == c) {
+        return (typed, find(selected + 1, &c.to_string()));
+    }
+    (typed, None)
+}
+
+/// Width of the size and date columns of the long listing.
+const LONG_META_WIDTH: usize = 22;
+
+/// Size and modification time at a fixed width, with a dash for a
+/// directory's size.
+fn long_meta(stat: Option<&Stat>) -> String {
+    let size = match stat.and_then(|stat| stat.size) {
+        Some(size) => human_size(size),
+        None => "-".to_string(),
+    };
+    // Blank when unknown or beyond what chrono can show.
+    let modified = stat
+        .and_then(|stat| stat.modified)
+        .and_then(local_time)
+        .map(|time| time.format("%b %e %H:%M").to_string())
+        .unwrap_or_default();
+    format!(" {:>8} {:>12}", size, modified)
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{}{}", bytes, UNITS[0]),
+        _ => format!("{:.1}{}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    "rwxrwxrwx"
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match mode & (0o400 >> i) {
+            0 => '-',
+            _ => c,
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &Metadata) -> String {
+    match metadata.permissions().readonly() {
+        true => "read-only".to_string(),
+        false => "read-write".to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn owner(metadata: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    format!("{}:{}", metadata.uid(), metadata.gid())
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &Metadata) -> String {
+    "-".to_string()
+}
+
+/// Moves the cursor up one entry, wrapping to the bottom of the list or
+/// stopping at the top.
+fn cursor_up(selected: usize, len: usize, wrap: bool) -> Option<usize> {
+    match len {
+        0 => None,
+        len if selected >= len => Some(len - 1),
+        len if selected == 0 && wrap => Some(len - 1),
+        _ => Some(selected.saturating_sub(1)),
+    }
+}
+
+/// Moves the cursor down one entry, wrapping to the top of the list or
+/// stopping at the bottom.
+fn cursor_down(selected: usize, len: usize, wrap: bool) -> Option<usize> {
+    match len {
+        0 => None,
+        len if selected + 1 >= len && wrap => Some(0),
+        len => Some((selected + 1).min(len - 1)),
+    }
+}
+
+/// Moves the cursor up a row of `columns` entries, staying put on the first.
+fn grid_up(selected: usize, columns: usize) -> Option<usize> {
+    Some(selected.checked_sub(columns).unwrap_or(selected))
+}
+
+/// Moves the cursor down a row, or to the last entry from the row above it.
+fn grid_down(selected: usize, len: usize, columns: usize) -> Option<usize> {
+    match len {
+        0 => None,
+        len if selected / columns == (len - 1) / columns => Some(selected.min(len - 1)),
+        len => Some((selected + columns).min(len - 1)),
+    }
+}
+
+/// Where the scrollbar thumb sits on a track of `rows` cells, and how long it
+/// is, or `None` when every entry fits.
+fn scroll_thumb(selected: Option<usize>, len: usize, rows: u16) -> Option<(u16, u16)> {
+    let rows_len = rows as usize;
+    if len <= rows_len || rows == 0 {
+        return None;
+    }
+    let length = (rows_len * rows_len / len).max(1);
+    let offset = selected.unwrap_or(0).min(len - 1) * (rows_len - length) / (len - 1);
+    Some((offset as u16, length as u16))
+}
+
+/// Keeps a selection in bounds after the listing changes to `len` entries.
+fn clamp_selection(selected: Option<usize>, len: usize) -> Option<usize> {
+    match len {
+        0 => None,
+        len => Some(selected.unwrap_or(0).min(len - 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crossterm::event::KeyModifiers;
+
+    #[test]
+    fn cursor_moves_within_list() {
+        for wrap in [true, false] {
+            assert_eq!(cursor_down(0, 3, wrap), Some(1));
+            assert_eq!(cursor_up(2, 3, wrap), Some(1));
+        }
+    }
+
+    #[test]
+    fn cursor_wraps_at_both_ends() {
+        assert_eq!(cursor_down(2, 3, true), Some(0));
+        assert_eq!(cursor_up(0, 3, true), Some(2));
+    }
+
+    #[test]
+    fn cursor_clamps_at_both_ends() {
+        assert_eq!(cursor_down(2, 3, false), Some(2));
+        assert_eq!(cursor_up(0, 3, false), Some(0));
+    }
+
+    #[test]
+    fn cursor_stays_on_single_entry() {
+        for wrap in [true, false] {
+            assert_eq!(cursor_down(0, 1, wrap), Some(0));
+            assert_eq!(cursor_up(0, 1, wrap), Some(0));
+        }
+    }
+
+    #[test]
+    fn cursor_on_empty_list_selects_nothing() {
+        for wrap in [true, false] {
+            assert_eq!(cursor_down(0, 0, wrap), None);
+            assert_eq!(cursor_up(0, 0, wrap), None);
+        }
+    }
+
+    #[test]
+    fn grid_cursor_moves_by_rows() {
+        // 0 1 2
+        // 3 4 5
+        // 6 7
+        assert_eq!(grid_down(1, 8, 3), Some(4));
+        assert_eq!(grid_down(5, 8, 3), Some(7));
+        assert_eq!(grid_down(7, 8, 3), Some(7));
+        assert_eq!(grid_up(4, 3), Some(1));
+        assert_eq!(grid_up(1, 3), Some(1));
+        assert_eq!(grid_down(0, 0, 3), None);
+    }
+
+    #[test]
+    fn selection_stays_valid_when_list_shrinks() {
+        assert_eq!(clamp_selection(Some(9), 3), Some(2));
+        assert_eq!(clamp_selection(Some(1), 3), Some(1));
+        assert_eq!(clamp_selection(Some(4), 0), None);
+        assert_eq!(clamp_selection(None, 2), Some(0));
+    }
+
+    #[test]
+    fn scroll_thumb_tracks_the_selection() {
+        assert_eq!(scroll_thumb(Some(0), 10, 20), None);
+        assert_eq!(scroll_thumb(Some(0), 100, 10), Some((0, 1)));
+        assert_eq!(scroll_thumb(Some(99), 100, 10), Some((9, 1)));
+        assert_eq!(scroll_thumb(Some(10), 20, 10), Some((2, 5)));
+    }
+
+    #[test]
+    fn history_steps_through_recent_queries() {
+        let mut history = History::default();
+        assert_eq!(history.older(), None);
+        history.push("foo");
+        history.push("bar");
+        history.push("foo");
+        assert_eq!(history.older(), Some("foo"));
+        assert_eq!(history.older(), Some("bar"));
+        assert_eq!(history.older(), Some("bar"));
+        assert_eq!(history.newer(), Some("foo"));
+        assert_eq!(history.newer(), Some(""));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn matches_are_found_ignoring_case() {
+        assert_eq!(find_ignore_case("Holiday.JPG", "jpg"), Some(8..11));
+        assert_eq!(find_ignore_case("Ärger.txt", "är"), Some(0..3));
+        assert_eq!(find_ignore_case("notes.md", "txt"), None);
+        assert_eq!(find_ignore_case("notes.md", ""), None);
+    }
+
+    #[test]
+    fn search_results_show_their_directory() {
+        let root = Path::new("/music");
+        assert_eq!(result_location(Path::new("/music/a.mp3"), root), ".");
+        assert_eq!(
+            result_location(Path::new("/music/live/2020/a.mp3"), root),
+            "./live/2020"
+        );
+    }
+
+    #[test]
+    fn elapsed_time_reads_naturally() {
+        assert_eq!(ago(Duration::from_secs(5)), "just now");
+        assert_eq!(ago(Duration::from_secs(3 * 60 + 10)), "3 min ago");
+        assert_eq!(ago(Duration::from_secs(2 * 3600)), "2 h ago");
+        assert_eq!(ago(Duration::from_secs(86400)), "1 day ago");
+        assert_eq!(ago(Duration::from_secs(15 * 86400)), "15 days ago");
+    }
+
+    #[test]
+    fn tags_keep_their_colour() {
+        assert_eq!(tag_color("receipts"), tag_color("receipts"));
+        let colors: Vec<_> = ["a", "b", "c", "d"].iter().map(|t| tag_color(t)).collect();
+        assert!(colors.iter().any(|color| *color != colors[0]));
+        let plain = tag_pills(&["rock".to_string()], Theme { color: false });
+        assert_eq!(plain[0].content, "[rock]");
+    }
+
+    #[test]
+    fn long_names_lose_their_middle() {
+        assert_eq!(
+            truncate_middle("verylongfilename.txt", 20),
+            "verylongfilename.txt"
+        );
+        assert_eq!(
+            truncate_middle("verylongfilename.txt", 15),
+            "verylon…ame.txt"
+        );
+        assert_eq!(truncate_middle("verylongfilename.txt", 1), "…");
+        assert_eq!(truncate_middle("verylongfilename.txt", 0), "");
+    }
+
+    #[test]
+    fn keys_are_bound_once_and_hinted() {
+        let keys: Vec<_> = MAIN_KEYS.iter().chain(TABLE_KEYS).map(|b| b.0).collect();
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{:?} bound twice", key);
+        }
+        assert_eq!(
+            hint_line(TAGGING_KEYS.iter().copied()),
+            "(Enter) save (Tab) accept suggestion (Backspace) remove tag (Esc) cancel"
+        );
+        assert!(!hint_line(LIST_KEYS.iter().map(|&(key, _, hint)| (key, hint))).contains("(q)"));
+    }
+
+    fn tagging(storage: &Rc<MemoryStorage>, read_only: bool) -> TaggingContext {
+        let config = Config {
+            read_only,
+            ..Config::default()
+        };
+        TaggingContext::new(storage.clone(), Rc::new(config))
+    }
+
+    fn press(ctx: &mut TaggingContext, keys: &str) {
+        let di = DirInfo::new("/music".to_string(), 0);
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\x08' => KeyCode::Backspace,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            ctx.handle_key(KeyEvent::new(code, KeyModifiers::NONE), &di);
+        }
+    }
+
+    #[test]
+    fn typed_tags_are_saved() {
+        let storage = Rc::new(MemoryStorage::default());
+        let mut ctx = tagging(&storage, false);
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        press(&mut ctx, "rock, live\n");
+        let path = Path::new("/music/a.mp3");
+        assert_eq!(storage.tags_for(path).unwrap(), ["live", "rock"]);
+        assert_eq!(ctx.tags, ["live", "rock"]);
+        assert!(ctx.tag_input.is_empty());
+    }
+
+    #[test]
+    fn suggestions_skip_tags_already_applied() {
+        let storage = Rc::new(MemoryStorage::default());
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        storage
+            .add_tags(Path::new("/music/a.mp3"), &tags(&["rock"]))
+            .unwrap();
+        storage
+            .add_tags(Path::new("/music/b.mp3"), &tags(&["rock", "jazz"]))
+            .unwrap();
+        let mut ctx = tagging(&storage, false);
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        assert_eq!(ctx.suggestions, ["audio", "jazz"]);
+    }
+
+    #[test]
+    fn directories_are_tagged_without_extension_guesses() {
+        let directory = env::temp_dir().join(format!("tidy-album-{}.mp3", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let storage = Rc::new(MemoryStorage::default());
+        let mut ctx = tagging(&storage, false);
+        ctx.send(Msg::File(directory.clone()));
+        assert!(ctx.suggestions.is_empty());
+        press(&mut ctx, "archived\n");
+        assert_eq!(storage.tags_for(&directory).unwrap(), ["archived"]);
+        fs::remove_dir(&directory).unwrap();
+    }
+
+    #[test]
+    fn typed_tags_are_kept_for_the_same_file() {
+        let storage = Rc::new(MemoryStorage::default());
+        let mut ctx = tagging(&storage, false);
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        press(&mut ctx, "live\x1b");
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        assert_eq!(ctx.tag_input, "live");
+        ctx.send(Msg::File(PathBuf::from("/music/b.mp3")));
+        assert_eq!(ctx.tag_input, "");
+    }
+
+    #[test]
+    fn backspace_on_empty_input_removes_last_tag() {
+        let storage = Rc::new(MemoryStorage::default());
+        let mut ctx = tagging(&storage, false);
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        press(&mut ctx, "live rock\n\x08");
+        assert_eq!(
+            storage.tags_for(Path::new("/music/a.mp3")).unwrap(),
+            ["live"]
+        );
+    }
+
+    #[test]
+    fn read_only_mode_saves_nothing() {
+        let storage = Rc::new(MemoryStorage::default());
+        let mut ctx = tagging(&storage, true);
+        ctx.send(Msg::File(PathBuf::from("/music/a.mp3")));
+        press(&mut ctx, "rock\n");
+        assert!(storage.tags.borrow().is_empty());
+        assert_eq!(ctx.error.as_deref(), Some("Read-only mode"));
+    }
+
+    #[test]
+    fn notes_are_edited_in_place() {
+        let storage = Rc::new(MemoryStorage::default());
+        let path = Path::new("/music/a.mp3");
+        storage.set_note(path, "needs review").unwrap();
+        let mut ctx = NoteContext::new(storage.clone(), Rc::new(Config::default()));
+        ctx.send(Msg::File(path.to_path_buf()));
+        assert_eq!(ctx.input, "needs review");
+        let di = DirInfo::new("/music".to_string(), 0);
+        let codes = std::iter::repeat_n(KeyCode::Backspace, 7).chain(Some(KeyCode::Enter));
+        for code in codes {
+            ctx.handle_key(KeyEvent::new(code, KeyModifiers::NONE), &di);
+        }
+        assert_eq!(storage.note(path).unwrap().as_deref(), Some("needs"));
+    }
+
+    #[test]
+    fn search_walks_in_the_background_down_to_max_depth() {
+        let root = env::temp_dir().join(format!("tidy-search-{}", process::id()));
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("top.txt"), "").unwrap();
+        fs::write(root.join("a/mid.txt"), "").unwrap();
+        fs::write(root.join("a/b/deep.txt"), "").unwrap();
+        let config = Config {
+            max_depth: 2,
+            ..Config::default()
+        };
+        let mut ctx = SearchContext::new(Rc::new(config));
+        ctx.send(Msg::Directory(root.clone()));
+        while ctx.incoming.is_some() {
+            ctx.receive();
+        }
+        assert_eq!(ctx.results, [root.join("a/mid.txt"), root.join("top.txt")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn renames_stay_in_their_directory() {
+        let root = env::temp_dir().join(format!("tidy-rename-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let from = root.join("a.txt");
+        fs::write(&from, "a").unwrap();
+        let storage = Rc::new(MemoryStorage::default());
+        storage.add_tags(&from, &["draft".to_string()]).unwrap();
+        let mut ctx = RenameContext::new(storage.clone(), Rc::new(Config::default()));
+        let di = DirInfo::new(root.to_string_lossy().into_owned(), 0);
+        let mut rename_to = |name: &str| {
+            ctx.send(Msg::File(from.clone()));
+            ctx.input = name.to_string();
+            let signal = ctx.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE), &di);
+            (signal.is_some(), ctx.error.take())
+        };
+
+        for name in ["../evil", "sub/b.txt", "..", ""] {
+            let (renamed, error) = rename_to(name);
+            assert!(!renamed && error.is_some(), "{:?} was accepted", name);
+        }
+        assert!(from.exists());
+        assert!(rename_to("b.txt").0);
+        assert!(!from.exists());
+        assert_eq!(storage.tags_for(&root.join("b.txt")).unwrap(), ["draft"]);
+
+        // Changing only the case takes on case-insensitive filesystems too.
+        let from = root.join("b.txt");
+        ctx.send(Msg::File(from.clone()));
+        ctx.input = "B.txt".to_string();
+        assert!(ctx.rename().is_some(), "{:?}", ctx.error);
+        let names: Vec<_> = fs::read_dir(&root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["B.txt"]);
+        fs::write(root.join("c.txt"), "c").unwrap();
+        ctx.send(Msg::File(root.join("B.txt")));
+        ctx.input = "c.txt".to_string();
+        assert!(ctx.rename().is_none());
+        assert_eq!(ctx.error.as_deref(), Some("c.txt already exists"));
+
+        storage.set_protected(&root.join("B.txt"), true).unwrap();
+        ctx.input = "d.txt".to_string();
+        assert!(ctx.rename().is_none());
+        assert!(ctx.error.take().unwrap().ends_with("is protected"));
+        assert!(root.join("B.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn protected_entries_inside_the_targets_are_refused() {
+        let storage = MemoryStorage::default();
+        let (photos, kept) = (
+            Path::new("/album/photos"),
+            Path::new("/album/photos/kept.jpg"),
+        );
+        let targets = [PathBuf::from("/album/notes.txt"), photos.to_path_buf()];
+        assert_eq!(protection(&storage, &targets), None);
+        storage.set_protected(kept, true).unwrap();
+        assert_eq!(
+            protection(&storage, &targets).as_deref(),
+            Some("/album/photos/kept.jpg is protected")
+        );
+        assert_eq!(protection(&storage, &targets[..1]), None);
+    }
+
+    #[test]
+    fn typing_jumps_to_matching_names() {
+        let files: Vec<_> = ["apple", "banana", "blueberry", "cherry"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let (typed, found) = type_ahead(&files, 0, String::new(), 'b');
+        assert_eq!(found, Some(1));
+        assert_eq!(type_ahead(&files, 1, typed.clone(), 'l').1, Some(2));
+        // Repeating the letter moves on to the next name starting with it.
+        let (typed, found) = type_ahead(&files, 1, typed, 'b');
+        assert_eq!(found, Some(2));
+        assert_eq!(type_ahead(&files, 2, typed, 'b').1, Some(1));
+        assert_eq!(type_ahead(&files, 0, String::new(), 'z').1, None);
+    }
+
+    #[test]
+    fn focus_moves_between_shown_panels() {
+        assert_eq!(cycle_focus(Panel::Files, true, true, true), Panel::Preview);
+        assert_eq!(cycle_focus(Panel::Info, true, true, true), Panel::Files);
+        assert_eq!(cycle_focus(Panel::Files, false, true, true), Panel::Info);
+        // Hidden panels are skipped.
+        assert_eq!(cycle_focus(Panel::Files, true, false, true), Panel::Info);
+        assert_eq!(cycle_focus(Panel::Files, true, false, false), Panel::Files);
+    }
+
+    #[test]
+    fn doubled_keys_delete_and_yank() {
+        let sequence = |first, second| key_sequence(first, KeyCode::Char(second));
+        assert!(sequence('d', 'd') == Some(Command::Delete));
+        assert!(sequence('y', 'y') == Some(Command::Yank));
+        assert!(sequence('g', '/') == Some(Command::Root));
+        // Anything else starts over with the second key.
+        assert!(sequence('d', 'y').is_none());
+        assert!(sequence('y', 'j').is_none());
+    }
+
+    #[test]
+    fn long_listing_columns_line_up() {
+        let day = SystemTime::UNIX_EPOCH + Duration::from_secs(86_400 * 400);
+        let file = Stat {
+            modified: Some(day),
+            size: Some(123_456),
+        };
+        let directory = Stat {
+            modified: Some(day),
+            size: None,
+        };
+        // Far beyond the dates chrono handles.
+        let distant = Stat {
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(u64::MAX / 4)),
+            size: Some(1),
+        };
+        for stat in [Some(&file), Some(&directory), Some(&distant), None] {
+            assert_eq!(long_meta(stat).chars().count(), LONG_META_WIDTH);
+        }
+    }
+
+    #[test]
+    fn large_files_are_not_read() {
+        let file = env::temp_dir().join(format!("tidy-large-{}.txt", process::id()));
+        fs::write(&file, "0123456789").unwrap();
+        assert_eq!(too_large(&file, 10), None);
+        assert!(too_large(&file, 9).unwrap().starts_with("(too large"));
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn preview_stops_at_the_line_limit() {
+        let file = env::temp_dir().join(format!("tidy-preview-{}", process::id()));
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        assert_eq!(preview_str(&file, 2), "one\ntwo");
+        fs::write(&file, b"\x7fELF\0\0").unwrap();
+        assert_eq!(preview_str(&file, 2), "(binary)");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn info_fields_follow_the_config() {
+        let file = env::temp_dir().join(format!("tidy-info-{}", process::id()));
+        fs::write(&file, "hello").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        let config = Config::default();
+        let lines = |fields: &[InfoField], hash| {
+            info_lines(
+                fields,
+                |field| field_str(field, &metadata, &config, hash),
+                Some(Spans::from("Tags: none")),
+                Some("First seen: today".to_string()),
+            )
+            .iter()
+            .map(|line| line.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect::<Vec<String>>()
+        };
+        let fields = [InfoField::Type, InfoField::Hash, InfoField::Tags];
+        assert_eq!(
+            lines(&fields, None),
+            [
+                "Type: file, Hash: hashing…",
+                "Tags: none",
+                "First seen: today"
+            ]
+        );
+        let fields = [InfoField::Tags, InfoField::Size, InfoField::Hash];
+        assert_eq!(
+            lines(&fields, Some("a430d84680aabd0b")),
+            [
+                "Tags: none",
+                "Size: 5B, Hash: a430d84680aabd0b, First seen: today"
+            ]
+        );
+
+        let hash = |limit, cancel| hash_str(&file, limit, &AtomicBool::new(cancel));
+        assert_eq!(hash(10, false).unwrap(), "a430d84680aabd0b");
+        assert!(hash(4, false).unwrap().starts_with("(too large"));
+        assert_eq!(hash(10, true), None);
+        fs::remove_file(&file).unwrap();
+    }
 }
@@ -0,0 +1,681 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use crate::{
+    ctx::ContextId,
+    filter::Stat,
+    paths,
+    storage::{Reindexed, Result, Storage},
+    Msg, Signal,
+};
+
+/// How many files are indexed between progress updates.
+const INDEX_PROGRESS: usize = 500;
+
+/// Shared-cache name for the in-memory database, so the indexing thread's
+/// connection sees the same data as the UI's.
+const MEMORY_URI: &str = "file:tidy?mode=memory&cache=shared";
+
+/// Where the tag database lives.
+#[derive(Clone, Debug)]
+pub enum Database {
+    File(PathBuf),
+    /// Nothing is written to disk; the data is gone once the last connection
+    /// closes.
+    Memory,
+}
+
+impl Database {
+    /// The in-memory database for `--memory`, otherwise the file at `db` or
+    /// the default location, creating its directory.
+    pub fn choose(memory: bool, db: Option<PathBuf>) -> Result<Database> {
+        if memory {
+            return Ok(Database::Memory);
+        }
+        let path = db
+            .or_else(paths::database_file)
+            .unwrap_or_else(|| PathBuf::from("tidy.db"));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Database::File(path))
+    }
+
+    pub fn open(&self) -> Result<SqliteStorage> {
+        let conn = match self {
+            Database::File(path) => Connection::open(path)?,
+            Database::Memory => Connection::open_with_flags(
+                MEMORY_URI,
+                OpenFlags::SQLITE_OPEN_READ_WRITE
+                    | OpenFlags::SQLITE_OPEN_CREATE
+                    | OpenFlags::SQLITE_OPEN_URI,
+            )?,
+        };
+        let storage = SqliteStorage { conn };
+        storage.init()?;
+        Ok(storage)
+    }
+}
+
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+
+    fn init(&self) -> Result<()> {
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS dirs (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL UNIQUE,
+                indexed_at INTEGER
+            )",
+            [],
+        )?;
+        self.add_column("dirs", "indexed_at")?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS files (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL,
+                path_id INTEGER NOT NULL REFERENCES dirs(id),
+                first_seen INTEGER,
+                size INTEGER,
+                modified INTEGER
+            )",
+            [],
+        )?;
+        self.migrate_files()?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS notes (
+                path TEXT PRIMARY KEY,
+                note TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS protected (
+                path TEXT PRIMARY KEY
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS operations (
+                id INTEGER PRIMARY KEY,
+                dir_id INTEGER NOT NULL REFERENCES dirs(id),
+                at INTEGER NOT NULL,
+                outcome TEXT NOT NULL
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS file_tags (
+                path TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                UNIQUE(path, tag)
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Brings a `files` table from before `first_seen` up to date: adds the
+    /// columns (left empty for files indexed back then; `size` and
+    /// `modified` are only filled in by reindexing) and drops the
+    /// duplicate rows re-indexing used to add, so each path is seen once.
+    fn migrate_files(&self) -> Result<()> {
+        self.add_column("files", "first_seen")?;
+        self.add_column("files", "size")?;
+        self.add_column("files", "modified")?;
+        self.conn.execute(
+            "DELETE FROM files WHERE id NOT IN (SELECT min(id) FROM files GROUP BY path)",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS files_path ON files(path)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Adds an integer `column` to `table` if it was created before the
+    /// column existed. Existing rows are left empty.
+    fn add_column(&self, table: &str, column: &str) -> Result<()> {
+        let exists = self
+            .conn
+            .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+            .exists([table, column])?;
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} INTEGER", table, column),
+                [],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Seconds since the epoch, as timestamps are stored.
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
+}
+
+fn from_secs(secs: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+impl Storage for SqliteStorage {
+    fn index_directory(
+        &self,
+        directory: &str,
+        files: &[PathBuf],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<()> {
+        // One commit for the lot; committing each insert makes big
+        // directories take minutes.
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("INSERT OR IGNORE INTO dirs (path) VALUES (?)", [directory])?;
+
+        let mut select = tx.prepare("SELECT id FROM dirs WHERE path = ?")?;
+
+        if let Some(Ok(name)) = select
+            .query_map::<u32, _, _>([directory], |row| row.get(0))?
+            .next()
+        {
+            let now = now_secs();
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO files (path, path_id, first_seen) VALUES (?, ?, ?)",
+            )?;
+            for (i, path) in files.iter().enumerate() {
+                stmt.execute(params![path.to_string_lossy(), name, now])?;
+                progress(i + 1);
+            }
+            tx.execute(
+                "UPDATE dirs SET indexed_at = ? WHERE id = ?",
+                params![now, name],
+            )?;
+        }
+        drop(select);
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn reindex_directory(&self, directory: &Path) -> Result<Reindexed> {
+        let directory_str = directory.to_string_lossy();
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO dirs (path) VALUES (?)",
+            [&directory_str],
+        )?;
+        let id: i64 = tx.query_row(
+            "SELECT id FROM dirs WHERE path = ?",
+            [&directory_str],
+            |row| row.get(0),
+        )?;
+        let mut stale: HashSet<String> = tx
+            .prepare("SELECT path FROM files WHERE path_id = ?")?
+            .query_map([id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let now = now_secs();
+        let mut reindexed = Reindexed::default();
+        {
+            let mut update =
+                tx.prepare("UPDATE files SET size = ?, modified = ? WHERE path = ?")?;
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO files (path, path_id, first_seen, size, modified)
+                VALUES (?, ?, ?, ?, ?)",
+            )?;
+            let mut delete = tx.prepare("DELETE FROM files WHERE path = ?")?;
+            for entry in fs::read_dir(directory)?.flatten() {
+                let path = entry.path().to_string_lossy().into_owned();
+                let stat = Stat::read(&entry.path());
+                let size = stat.and_then(|stat| stat.size).map(|size| size as i64);
+                let modified = stat
+                    .and_then(|stat| stat.modified)
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|since| since.as_secs() as i64);
+                if stale.remove(&path) {
+                    update.execute(params![size, modified, path])?;
+                } else {
+                    reindexed.added += insert.execute(params![path, id, now, size, modified])?;
+                }
+            }
+            for path in &stale {
+                reindexed.removed += delete.execute([path])?;
+            }
+        }
+        tx.execute(
+            "UPDATE dirs SET indexed_at = ? WHERE id = ?",
+            params![now, id],
+        )?;
+        tx.commit()?;
+        Ok(reindexed)
+    }
+
+    fn record_operation(&self, directory: &Path, outcome: &str) -> Result<()> {
+        let directory = directory.to_string_lossy();
+        self.conn
+            .execute("INSERT OR IGNORE INTO dirs (path) VALUES (?)", [&directory])?;
+        self.conn.execute(
+            "INSERT INTO operations (dir_id, at, outcome)
+            SELECT id, ?, ? FROM dirs WHERE path = ?",
+            params![now_secs(), outcome, directory],
+        )?;
+        Ok(())
+    }
+
+    fn last_operation(&self, directory: &Path) -> Result<Option<(SystemTime, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, outcome FROM operations JOIN dirs ON dirs.id = dir_id
+            WHERE dirs.path = ? ORDER BY at DESC, operations.id DESC LIMIT 1",
+        )?;
+        let last = stmt
+            .query_map([directory.to_string_lossy()], |row| {
+                Ok((from_secs(row.get(0)?), row.get(1)?))
+            })?
+            .next()
+            .transpose()?;
+        Ok(last)
+    }
+
+    fn indexed_at(&self, directory: &Path) -> Result<Option<SystemTime>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT indexed_at FROM dirs WHERE path = ?")?;
+        let indexed: Option<Option<i64>> = stmt
+            .query_map([directory.to_string_lossy()], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        Ok(indexed.flatten().map(from_secs))
+    }
+
+    fn first_seen(&self, path: &Path) -> Result<Option<SystemTime>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT first_seen FROM files WHERE path = ?")?;
+        let seen: Option<Option<i64>> = stmt
+            .query_map([path.to_string_lossy()], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        Ok(seen.flatten().map(from_secs))
+    }
+
+    fn tags_for(&self, path: &Path) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT tag FROM file_tags WHERE path = ? ORDER BY tag")?;
+        let tags = stmt.query_map([path.to_string_lossy()], |row| row.get(0))?;
+        Ok(tags.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn add_tags(&self, path: &Path, tags: &[String]) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("INSERT OR IGNORE INTO file_tags (path, tag) VALUES (?, ?)")?;
+        for tag in tags {
+            stmt.execute(params![path.to_string_lossy(), tag])?;
+        }
+        Ok(())
+    }
+
+    fn add_tags_batch(&self, paths: &[PathBuf], tags: &[String]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt =
+                tx.prepare("INSERT OR IGNORE INTO file_tags (path, tag) VALUES (?, ?)")?;
+            for path in paths {
+                for tag in tags {
+                    stmt.execute(params![path.to_string_lossy(), tag])?;
+                }
+            }
+        }
+        Ok(tx.commit()?)
+    }
+
+    fn remove_tag(&self, path: &Path, tag: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM file_tags WHERE path = ? AND tag = ?",
+            params![path.to_string_lossy(), tag],
+        )?;
+        Ok(())
+    }
+
+    fn all_tags(&self) -> Result<Vec<(PathBuf, Vec<String>)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, tag FROM file_tags ORDER BY path, tag")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut tagged: Vec<(PathBuf, Vec<String>)> = Vec::new();
+        for row in rows {
+            let (path, tag) = row?;
+            let path = PathBuf::from(path);
+            match tagged.last_mut() {
+                Some((last, tags)) if *last == path => tags.push(tag),
+                _ => tagged.push((path, vec![tag])),
+            }
+        }
+        Ok(tagged)
+    }
+
+    fn tagged_in(&self, directory: &Path) -> Result<HashSet<PathBuf>> {
+        let prefix = directory.join("").to_string_lossy().into_owned();
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT path FROM file_tags
+            WHERE substr(path, 1, length(?1)) = ?1 AND instr(substr(path, length(?1) + 1), ?2) = 0",
+        )?;
+        let paths = stmt.query_map(
+            params![prefix, std::path::MAIN_SEPARATOR.to_string()],
+            |row| row.get::<_, String>(0).map(PathBuf::from),
+        )?;
+        Ok(paths.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn sibling_tags(&self, directory: &Path, limit: usize) -> Result<Vec<String>> {
+        let prefix = directory.join("").to_string_lossy().into_owned();
+        let mut stmt = self.conn.prepare(
+            "SELECT tag FROM file_tags
+            WHERE substr(path, 1, length(?1)) = ?1 AND instr(substr(path, length(?1) + 1), ?2) = 0
+            GROUP BY tag ORDER BY count(*) DESC, tag LIMIT ?3",
+        )?;
+        let tags = stmt.query_map(
+            params![prefix, std::path::MAIN_SEPARATOR.to_string(), limit as i64],
+            |row| row.get(0),
+        )?;
+        Ok(tags.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn set_protected(&self, path: &Path, protected: bool) -> Result<()> {
+        let sql = match protected {
+            true => "INSERT OR IGNORE INTO protected (path) VALUES (?)",
+            false => "DELETE FROM protected WHERE path = ?",
+        };
+        self.conn.execute(sql, [path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    fn protected_in(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        let prefix = path.join("").to_string_lossy().into_owned();
+        let mut stmt = self.conn.prepare(
+            "SELECT path FROM protected WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+        )?;
+        let paths = stmt.query_map(params![path.to_string_lossy(), prefix], |row| {
+            row.get::<_, String>(0).map(PathBuf::from)
+        })?;
+        Ok(paths.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn note(&self, path: &Path) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT note FROM notes WHERE path = ?")?;
+        let note = stmt
+            .query_map([path.to_string_lossy()], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        Ok(note)
+    }
+
+    fn set_note(&self, path: &Path, note: &str) -> Result<()> {
+        match note.is_empty() {
+            true => self
+                .conn
+                .execute("DELETE FROM notes WHERE path = ?", [path.to_string_lossy()])?,
+            false => self.conn.execute(
+                "INSERT OR REPLACE INTO notes (path, note) VALUES (?, ?)",
+                params![path.to_string_lossy(), note],
+            )?,
+        };
+        Ok(())
+    }
+
+    fn move_path(&self, from: &Path, to: &Path) -> Result<()> {
+        let prefix = from.join("").to_string_lossy().into_owned();
+        let from = from.to_string_lossy();
+        let to = to.to_string_lossy();
+        let tx = self.conn.unchecked_transaction()?;
+        // `path` itself and everything below it; LIKE would trip over `%`
+        // and `_` in names.
+        for table in ["files", "file_tags", "notes", "protected"] {
+            tx.execute(
+                &format!(
+                    "UPDATE OR REPLACE {} SET path = ?2 || substr(path, length(?1) + 1)
+                     WHERE path = ?1 OR substr(path, 1, length(?3)) = ?3",
+                    table
+                ),
+                params![from, to, prefix],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn setting(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT value FROM settings WHERE key = ?")?;
+        let value = stmt
+            .query_map([key], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        Ok(value)
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)",
+            [key, value],
+        )?;
+        Ok(())
+    }
+}
+
+/// Indexes a directory on its own connection so the UI keeps drawing.
+pub fn spawn_index(
+    database: Database,
+    directory: String,
+    files: Vec<PathBuf>,
+    sender: mpsc::Sender<Signal>,
+) {
+    thread::spawn(move || {
+        let total = files.len();
+        let result = database.open().and_then(|storage| {
+            storage.index_directory(&directory, &files, &mut |done| {
+                if done % INDEX_PROGRESS == 0 {
+                    let _ = sender.send(Signal::Status(format!(
+                        "Indexing {}/{} files…",
+                        done, total
+                    )));
+                }
+            })
+        });
+        let _ = sender.send(Signal::Message(ContextId::Main, Msg::Stored));
+        let _ = sender.send(Signal::Status(match result {
+            Ok(()) => format!("Indexed {} files", total),
+            Err(e) => format!("Indexing failed: {}", e),
+        }));
+    });
+}
+
+/// Reindexes `directory` on its own connection, reporting what changed.
+pub fn spawn_reindex(database: Database, directory: PathBuf, sender: mpsc::Sender<Signal>) {
+    thread::spawn(move || {
+        let result = database
+            .open()
+            .and_then(|storage| storage.reindex_directory(&directory));
+        let message = match result {
+            Ok(reindexed) => format!(
+                "Reindexed {}: {} added, {} removed",
+                directory.display(),
+                reindexed.added,
+                reindexed.removed
+            ),
+            Err(e) => format!("Reindexing {} failed: {}", directory.display(), e),
+        };
+        let _ = sender.send(Signal::Message(ContextId::Main, Msg::Stored));
+        let _ = sender.send(Signal::Message(ContextId::Log, Msg::Log(message.clone())));
+        let _ = sender.send(Signal::Status(message));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn indexing_a_large_directory_commits_once() {
+        let root = env::temp_dir().join(format!("tidy-index-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let storage = Database::File(root.join("tidy.db")).open().unwrap();
+        let directory = root.join("big");
+        let files: Vec<_> = (0..50_000)
+            .map(|i| directory.join(format!("file-{}", i)))
+            .collect();
+
+        let mut done = 0;
+        storage
+            .index_directory(directory.to_str().unwrap(), &files, &mut |n| done = n)
+            .unwrap();
+        assert_eq!(done, files.len());
+        let count: usize = storage
+            .conn
+            .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, files.len());
+        assert!(storage.indexed_at(&directory).unwrap().is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn indexing_a_name_that_is_not_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let storage = Database::Memory.open().unwrap();
+        let directory = Path::new("/not-utf8");
+        let files = [
+            directory.join(OsStr::from_bytes(b"caf\xe9.txt")),
+            directory.join("plain.txt"),
+        ];
+        storage
+            .index_directory("/not-utf8", &files, &mut |_| {})
+            .unwrap();
+        assert!(storage.indexed_at(directory).unwrap().is_some());
+        let count: usize = storage
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM files WHERE path LIKE '/not-utf8/%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn sibling_tags_stop_at_the_limit() {
+        let sqlite = Database::Memory.open().unwrap();
+        let memory = crate::storage::MemoryStorage::default();
+        for storage in [&sqlite as &dyn Storage, &memory] {
+            for (file, tags) in [
+                ("a", &["x", "y", "z"][..]),
+                ("b", &["y", "z"]),
+                ("c", &["z"]),
+            ] {
+                let tags: Vec<_> = tags.iter().map(|tag| tag.to_string()).collect();
+                let path = Path::new("/siblings").join(file);
+                storage.add_tags(&path, &tags).unwrap();
+            }
+            let directory = Path::new("/siblings");
+            assert_eq!(storage.sibling_tags(directory, 2).unwrap(), ["z", "y"]);
+            assert_eq!(
+                storage.sibling_tags(directory, 10).unwrap(),
+                ["z", "y", "x"]
+            );
+        }
+    }
+
+    #[test]
+    fn moving_a_directory_carries_its_records() {
+        let storage = Database::Memory.open().unwrap();
+        let tags = ["draft".to_string()];
+        storage.add_tags(Path::new("/a/x"), &tags).unwrap();
+        storage.add_tags(Path::new("/ab/y"), &tags).unwrap();
+        storage.set_note(Path::new("/a"), "old name").unwrap();
+
+        storage.move_path(Path::new("/a"), Path::new("/b")).unwrap();
+        assert_eq!(storage.tags_for(Path::new("/b/x")).unwrap(), tags);
+        assert!(storage.tags_for(Path::new("/a/x")).unwrap().is_empty());
+        // Only a prefix by characters, not a path below `/a`.
+        assert_eq!(storage.tags_for(Path::new("/ab/y")).unwrap(), tags);
+        assert!(storage.note(Path::new("/b")).unwrap().is_some());
+    }
+
+    #[test]
+    fn operations_are_kept_per_directory() {
+        let storage = Database::Memory.open().unwrap();
+        let (photos, music) = (Path::new("/history/photos"), Path::new("/history/music"));
+        assert!(storage.last_operation(photos).unwrap().is_none());
+        storage
+            .record_operation(photos, "deleted 40 files")
+            .unwrap();
+        storage
+            .record_operation(photos, "copied 2 entries")
+            .unwrap();
+        storage.record_operation(music, "deleted 1 files").unwrap();
+
+        let (_, outcome) = storage.last_operation(photos).unwrap().unwrap();
+        assert_eq!(outcome, "copied 2 entries");
+        // Recording doesn't make a directory look indexed.
+        assert!(storage.indexed_at(photos).unwrap().is_none());
+    }
+
+    #[test]
+    fn reindexing_adds_and_removes_entries() {
+        let root = env::temp_dir().join(format!("tidy-reindex-{}", process::id()));
+        let directory = root.join("dir");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("a"), "a").unwrap();
+        fs::write(directory.join("b"), "b").unwrap();
+        let storage = Database::File(root.join("tidy.db")).open().unwrap();
+
+        let first = storage.reindex_directory(&directory).unwrap();
+        assert_eq!(
+            first,
+            Reindexed {
+                added: 2,
+                removed: 0
+            }
+        );
+        fs::remove_file(directory.join("a")).unwrap();
+        fs::write(directory.join("c"), "c").unwrap();
+        let second = storage.reindex_directory(&directory).unwrap();
+        assert_eq!(
+            second,
+            Reindexed {
+                added: 1,
+                removed: 1
+            }
+        );
+        assert!(storage.indexed_at(&directory).unwrap().is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
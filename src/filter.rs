@@ -0,0 +1,329 @@
+//! Narrowing the listing by what entries look like on disk, e.g. `age>6mo`
+//! for files nobody has touched in half a year, `size>100M` for the ones
+//! taking up the most space or `*.rs` for those with a matching name.
+
+use std::{
+    fmt, fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::config::glob_match;
+
+/// The parts of an entry's metadata filters look at, read once when it's
+/// listed.
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    pub modified: Option<SystemTime>,
+    /// In bytes; `None` for directories, whose own size says nothing about
+    /// their contents.
+    pub size: Option<u64>,
+}
+
+impl Stat {
+    pub fn read(path: &Path) -> Option<Stat> {
+        let metadata = fs::metadata(path)
+            .or_else(|_| fs::symlink_metadata(path))
+            .ok()?;
+        Some(Stat {
+            modified: metadata.modified().ok(),
+            size: Some(metadata.len()).filter(|_| !metadata.is_dir()),
+        })
+    }
+}
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+/// Suffixes accepted for ages, largest first so they're also used to print
+/// them.
+const AGE_UNITS: &[(&str, u64)] = &[
+    ("y", 365 * DAY),
+    ("mo", 30 * DAY),
+    ("w", 7 * DAY),
+    ("d", DAY),
+    ("h", HOUR),
+    ("min", MINUTE),
+    ("s", 1),
+];
+
+/// Suffixes accepted for sizes, largest first.
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("T", 1 << 40),
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+    ("", 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Age {
+    OlderThan(Duration),
+    NewerThan(Duration),
+}
+
+impl Age {
+    fn matches(self, modified: SystemTime, now: SystemTime) -> bool {
+        // Timestamps in the future count as brand new.
+        let age = now.duration_since(modified).unwrap_or_default();
+        match self {
+            Age::OlderThan(limit) => age > limit,
+            Age::NewerThan(limit) => age < limit,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Size {
+    LargerThan(u64),
+    SmallerThan(u64),
+}
+
+impl Size {
+    fn matches(self, size: u64) -> bool {
+        match self {
+            Size::LargerThan(limit) => size > limit,
+            Size::SmallerThan(limit) => size < limit,
+        }
+    }
+}
+
+/// Criteria an entry has to meet to stay in the listing. Empty by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub age: Option<Age>,
+    pub size: Option<Size>,
+    /// Only entries without any tags. Checked against the database by the
+    /// listing, since a `Stat` knows nothing about tags.
+    pub untagged: bool,
+    /// A shell-style pattern names have to match, like `*.rs`.
+    pub name: Option<String>,
+}
+
+impl Filter {
+    /// Parses whitespace separated terms like `age>6mo`, `age<1d`,
+    /// `size>100M`, `untagged` or a name pattern containing `*` or `?`.
+    pub fn parse(input: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for term in input.split_whitespace() {
+            if term == "untagged" {
+                filter.untagged = true;
+            } else if let Some(limit) = term.strip_prefix("age>") {
+                filter.age = Some(Age::OlderThan(parse_age(limit)?));
+            } else if let Some(limit) = term.strip_prefix("age<") {
+                filter.age = Some(Age::NewerThan(parse_age(limit)?));
+            } else if let Some(limit) = term.strip_prefix("size>") {
+                filter.size = Some(Size::LargerThan(parse_size(limit)?));
+            } else if let Some(limit) = term.strip_prefix("size<") {
+                filter.size = Some(Size::SmallerThan(parse_size(limit)?));
+            } else if term.contains(['*', '?'].as_ref()) {
+                filter.name = Some(term.to_string());
+            } else {
+                return Err(format!("Unknown filter: {}", term));
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Filter::default()
+    }
+
+    /// Whether `file`'s name matches the name pattern, if there is one.
+    pub fn matches_name(&self, file: &Path) -> bool {
+        let pattern = match &self.name {
+            Some(pattern) => pattern,
+            None => return true,
+        };
+        file.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| glob_match(pattern, name))
+    }
+
+    /// Whether an entry with `stat` passes. Entries that couldn't be read
+    /// only pass an empty filter.
+    pub fn matches(&self, stat: Option<&Stat>, now: SystemTime) -> bool {
+        if self.age.is_none() && self.size.is_none() {
+            return true;
+        }
+        let stat = match stat {
+            Some(stat) => stat,
+            None => return false,
+        };
+        let age = match (self.age, stat.modified) {
+            (Some(age), Some(modified)) => age.matches(modified, now),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let size = match (self.size, stat.size) {
+            (Some(limit), Some(size)) => limit.matches(size),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        age && size
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms = vec![];
+        if let Some(name) = &self.name {
+            terms.push(name.clone());
+        }
+        match self.age {
+            Some(Age::OlderThan(limit)) => terms.push(format!("age>{}", format_age(limit))),
+            Some(Age::NewerThan(limit)) => terms.push(format!("age<{}", format_age(limit))),
+            None => {}
+        }
+        match self.size {
+            Some(Size::LargerThan(limit)) => terms.push(format!("size>{}", format_size(limit))),
+            Some(Size::SmallerThan(limit)) => terms.push(format!("size<{}", format_size(limit))),
+            None => {}
+        }
+        if self.untagged {
+            terms.push("untagged".to_string());
+        }
+        write!(f, "{}", terms.join(" "))
+    }
+}
+
+fn parse_age(input: &str) -> Result<Duration, String> {
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (count, unit) = input.split_at(digits);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("Expected an age like 6mo, got {:?}", input))?;
+    let seconds = AGE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, seconds)| *seconds)
+        .ok_or_else(|| format!("Unknown unit {:?}, expected one of y mo w d h min s", unit))?;
+    Ok(Duration::from_secs(count.saturating_mul(seconds)))
+}
+
+/// Prints an age in the largest unit it's a whole number of.
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    AGE_UNITS
+        .iter()
+        .find(|(_, unit)| seconds.is_multiple_of(*unit) && seconds >= *unit)
+        .map(|(name, unit)| format!("{}{}", seconds / unit, name))
+        .unwrap_or_else(|| format!("{}s", seconds))
+}
+
+/// Parses a size like `100M`, with an optional K, M, G or T (powers of 1024).
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (count, unit) = input.split_at(digits);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| format!("Expected a size like 100M, got {:?}", input))?;
+    let bytes = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+        .map(|(_, bytes)| *bytes)
+        .ok_or_else(|| format!("Unknown unit {:?}, expected one of K M G T", unit))?;
+    Ok(count.saturating_mul(bytes))
+}
+
+fn format_size(size: u64) -> String {
+    SIZE_UNITS
+        .iter()
+        .find(|(_, unit)| size.is_multiple_of(*unit) && size >= *unit)
+        .map(|(name, unit)| format!("{}{}", size / unit, name))
+        .unwrap_or_else(|| size.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages_parse_with_units() {
+        assert_eq!(
+            Filter::parse("age>6mo").unwrap().age,
+            Some(Age::OlderThan(Duration::from_secs(6 * 30 * DAY)))
+        );
+        assert_eq!(
+            Filter::parse("age<1d").unwrap().age,
+            Some(Age::NewerThan(Duration::from_secs(DAY)))
+        );
+        assert!(Filter::parse("age>6").is_err());
+        assert!(Filter::parse("age>mo").is_err());
+        assert!(Filter::parse("old").is_err());
+        assert!(Filter::parse("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn filters_print_as_they_parse() {
+        for input in [
+            "age>6mo",
+            "age<2w",
+            "age>90min",
+            "size>100M",
+            "age>1y size<1536",
+            "size>1K untagged",
+            "*.rs age<1d",
+            "",
+        ] {
+            assert_eq!(Filter::parse(input).unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn names_match_a_pattern() {
+        let filter = Filter::parse("*.rs").unwrap();
+        assert!(filter.matches_name(Path::new("/src/main.rs")));
+        assert!(!filter.matches_name(Path::new("/src/main.rs.bak")));
+        assert!(Filter::default().matches_name(Path::new("anything")));
+    }
+
+    #[test]
+    fn age_compares_against_now() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * DAY);
+        let stat = |days| Stat {
+            modified: Some(now - Duration::from_secs(days * DAY)),
+            size: Some(0),
+        };
+        let old = Filter::parse("age>30d").unwrap();
+        assert!(old.matches(Some(&stat(31)), now));
+        assert!(!old.matches(Some(&stat(1)), now));
+        assert!(!old.matches(None, now));
+        let recent = Filter::parse("age<1w").unwrap();
+        assert!(recent.matches(Some(&stat(1)), now));
+        assert!(!recent.matches(Some(&stat(8)), now));
+        assert!(Filter::default().matches(None, now));
+    }
+
+    #[test]
+    fn sizes_accept_human_units() {
+        assert_eq!(
+            Filter::parse("size>100M").unwrap().size,
+            Some(Size::LargerThan(100 << 20))
+        );
+        assert_eq!(
+            Filter::parse("size<2k").unwrap().size,
+            Some(Size::SmallerThan(2048))
+        );
+        assert!(Filter::parse("size>1P").is_err());
+        assert!(Filter::parse("size>").is_err());
+    }
+
+    #[test]
+    fn size_filters_skip_directories() {
+        let now = SystemTime::now();
+        let stat = |size| Stat {
+            modified: Some(now),
+            size,
+        };
+        let big = Filter::parse("size>1K").unwrap();
+        assert!(big.matches(Some(&stat(Some(4096))), now));
+        assert!(!big.matches(Some(&stat(Some(10))), now));
+        assert!(!big.matches(Some(&stat(None)), now));
+    }
+}
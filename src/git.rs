@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GitStatus {
+    Staged,
+    Modified,
+    Untracked,
+}
+
+impl GitStatus {
+    fn parse(code: &str) -> Option<GitStatus> {
+        let mut chars = code.chars();
+        match (chars.next()?, chars.next()?) {
+            ('?', '?') => Some(GitStatus::Untracked),
+            (_, y) if y != ' ' => Some(GitStatus::Modified),
+            (x, _) if x != ' ' => Some(GitStatus::Staged),
+            _ => None,
+        }
+    }
+
+    pub fn marker(self) -> char {
+        match self {
+            GitStatus::Staged => '+',
+            GitStatus::Modified => 'M',
+            GitStatus::Untracked => '?',
+        }
+    }
+}
+
+/// Runs `git status` for `directory` and maps each of its entries to the most
+/// pressing status found at or below it. Empty outside of a repository.
+pub fn statuses(directory: &Path) -> HashMap<PathBuf, GitStatus> {
+    let mut statuses = HashMap::new();
+    let root = match git(directory, &["rev-parse", "--show-toplevel"]) {
+        Some(root) => PathBuf::from(root.trim_end()),
+        None => return statuses,
+    };
+    let output = match git(directory, &["status", "--porcelain", "-z", "--", "."]) {
+        Some(output) => output,
+        None => return statuses,
+    };
+
+    let mut records = output.split('\0');
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (code, path) = record.split_at(3);
+        if code.starts_with('R') || code.starts_with('C') {
+            // Renames and copies are followed by their source path.
+            records.next();
+        }
+        let status = match GitStatus::parse(code) {
+            Some(status) => status,
+            None => continue,
+        };
+        let path = root.join(path);
+        let entry = match path
+            .strip_prefix(directory)
+            .ok()
+            .and_then(|p| p.iter().next())
+        {
+            Some(name) => directory.join(name),
+            None => continue,
+        };
+        let current = statuses.entry(entry).or_insert(status);
+        if status == GitStatus::Modified || *current == GitStatus::Untracked {
+            *current = status;
+        }
+    }
+    statuses
+}
+
+fn git(directory: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .ok()?;
+    match output.status.success() {
+        true => String::from_utf8(output.stdout).ok(),
+        false => None,
+    }
+}
@@ -0,0 +1,80 @@
+//! Debug logging to a file, since the TUI owns the terminal. Off unless
+//! `--log` or `RUST_LOG` asks for it.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::Local;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::paths;
+
+struct FileLogger {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                Local::now().format("%F %T%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Starts appending to `path`, or to `tidy.log` in the data directory when
+/// only `RUST_LOG` is set. `RUST_LOG` picks the level, `debug` by default.
+pub fn init(path: Option<PathBuf>) -> io::Result<()> {
+    let level = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok());
+    let path = match (path, level) {
+        (Some(path), _) => path,
+        (None, Some(_)) => paths::log_file().unwrap_or_else(|| PathBuf::from("tidy.log")),
+        (None, None) => return Ok(()),
+    };
+    let level = level.unwrap_or(LevelFilter::Debug);
+    let logger = FileLogger {
+        file: Mutex::new(open(&path)?),
+        level,
+    };
+    // Only fails if a logger is already set, which leaves that one working.
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+    Ok(())
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
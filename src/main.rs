@@ -1,20 +1,49 @@
+mod cli;
+mod clipboard;
+mod config;
 mod ctx;
-use ctx::{Ctx, MainContext, TaggingContext};
+mod db;
+mod filter;
+mod git;
+mod logging;
+mod ops;
+mod paths;
+mod query;
+mod reveal;
+mod storage;
+mod theme;
+mod view;
+use config::{Config, Density, DirConfig, View};
+use ctx::{
+    ContextId, Ctx, FilterContext, GotoContext, LogContext, MainContext, NoteContext,
+    OpenWithContext, Panel, PathDisplay, RenameContext, SearchContext, SummaryContext,
+    TaggingContext,
+};
+use db::Database;
+use filter::{Filter, Stat};
+use git::GitStatus;
+use ops::{Operation, Record, Worker};
+use storage::Storage;
 
-use crossterm::event::{read, Event, KeyEvent};
-use rusqlite::{params, Connection, Result};
-use std::any::TypeId;
-use std::collections::HashMap;
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use rusqlite::Result;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs;
-use std::fs::DirEntry;
-use std::fs::ReadDir;
-use std::io::Error;
-use std::io::{self, Stdout};
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::io::{self, Stderr};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use structopt::StructOpt;
+use tui::layout::Rect;
+use tui::widgets::{ListState, Paragraph, Wrap};
 use tui::Frame;
-use tui::{backend::CrosstermBackend, widgets::ListState, Terminal};
+use tui::{backend::CrosstermBackend, Terminal};
 
 struct Channel {
     sender: mpsc::Sender<Signal>,
@@ -33,84 +62,640 @@ impl Channel {
     fn send(&mut self, msg: Signal) {
         match msg {
             Signal::And(s1, s2) => {
-                self.sender.send(*s1);
-                self.sender.send(*s2);
+                self.send(*s1);
+                self.send(*s2);
             }
             msg => {
-                self.sender.send(msg);
+                // The receiver lives as long as the channel, so this can't fail.
+                let _ = self.sender.send(msg);
             }
         }
     }
 }
+/// Time spent on the last frame, shown in a corner with `--debug`.
+#[derive(Default)]
+struct Timings {
+    render: Duration,
+    /// Handling keys and signals, without waiting for them.
+    events: Duration,
+}
+
 struct State {
     info: DirInfo,
-    context: TypeId,
+    context: ContextId,
     channel: Channel,
-    ctx_map: HashMap<TypeId, Box<dyn Ctx>>,
+    ctx_map: HashMap<ContextId, Box<dyn Ctx>>,
+    /// Messages sent to contexts not registered yet, delivered on `register`.
+    pending: HashMap<ContextId, Vec<Msg>>,
+    worker: Option<Worker>,
+    config: Rc<Config>,
+    database: Database,
+    storage: Rc<dyn Storage>,
+    /// The tags applied most recently, in any directory.
+    last_tags: Vec<String>,
+    /// How long the last frame took, kept with `--debug`.
+    timings: Option<Timings>,
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum Command {
     Quit,
     None,
     CursorUp,
     CursorDown,
+    CursorLeft,
+    CursorRight,
     Tag,
+    TagAll,
+    Yank,
+    Paste,
+    Delete,
+    Cancel,
+    Goto,
+    Home,
+    Root,
+    Pending(char),
+    TogglePaths,
+    Search,
+    Log,
+    Filter,
+    ToggleView,
+    /// Focus the next (or previous) table column.
+    FocusColumn(bool),
+    /// Move the focus to the next (or previous) panel.
+    FocusPanel(bool),
+    ResizeColumn(i16),
+    ToggleColumn(usize),
+    Copy(PathDisplay),
+    Edit,
+    Shell,
+    Expand,
+    TogglePreview,
+    ToggleInfo,
+    ToggleLong,
+    /// Jump to entries by typing the start of their name.
+    TypeAhead,
+    ToggleCommands,
+    Reveal,
+    ToggleUntagged,
+    TagAgain,
+    ToggleProtected,
+    /// Add the highlighted entry to the selection, or take it out.
+    Select,
+    /// Open the selection, or the highlighted entry, with its default app.
+    Open,
+    /// Pick one of the configured apps to open the highlighted entry with.
+    OpenWith,
+    Reindex,
+    ToggleDirsFirst,
+    ToggleRecursive,
+    Note,
+    Rename,
+    /// Run the configured command with this index on the highlighted entry.
+    Custom(usize),
+}
+
+impl Command {
+    /// Whether the command changes anything on disk or in the database.
+    fn mutates(&self) -> bool {
+        matches!(
+            self,
+            Command::Paste
+                | Command::Delete
+                | Command::ToggleProtected
+                | Command::Reindex
+                | Command::Note
+                | Command::Rename
+        )
+    }
 }
 
 pub enum Msg {
     File(PathBuf),
+    Directory(PathBuf),
+    Navigated,
+    Refreshed(usize),
+    /// Highlight the entry at this index.
+    Select(usize),
+    /// An entry for the session log.
+    Log(String),
+    /// Text to start an input prompt with.
+    Prefill(String),
+    /// Per-item results of an operation that partly failed.
+    Summary(ops::Summary),
+    /// Something shown from storage changed, like a note or the index.
+    Stored,
 }
 
 pub enum Signal {
     Quit,
-    Change(TypeId),
-    Message(TypeId, Msg),
+    Change(ContextId),
+    Message(ContextId, Msg),
     And(Box<Signal>, Box<Signal>),
+    Spawn(Operation),
+    Cancel,
+    Status(String),
+    Finished(String),
+    /// Add a finished operation to its directory's history.
+    Record(Record),
+    Navigate(PathBuf),
+    /// Re-read the current directory.
+    Refresh,
+    /// Replace the active filter.
+    Filter(Filter),
+    /// Highlight this entry once the directory being loaded is ready.
+    Reveal(PathBuf),
+    Entries(u64, Vec<(PathBuf, Option<Stat>)>),
+    Loaded(u64),
+    /// The directory being listed no longer exists.
+    Vanished(u64, PathBuf),
+    Git(u64, HashMap<PathBuf, GitStatus>),
+    /// Hands the terminal over to an external program until it exits.
+    Suspend(process::Command),
+    Clipboard(String),
+    /// Tags were just applied, to be remembered for `TagAgain`.
+    Tagged(Vec<String>),
+    /// Apply the last tags used to this file too.
+    TagAgain(PathBuf),
+    /// Protect this entry from deletion, or stop protecting it.
+    Protect(PathBuf),
+    /// Flip whether directories are listed first, remembering the choice.
+    DirsFirst,
+    /// Switch between the directory's entries and every file below it.
+    Recursive,
+    /// Sync the database's record of the current directory with the disk.
+    Reindex,
 }
 
 impl Signal {
     fn and(self, other: Signal) -> Signal {
         Signal::And(Box::new(self), Box::new(other))
     }
+
+    /// A one-line summary for the debug log, leaving out bulky payloads.
+    fn describe(&self) -> String {
+        match self {
+            Signal::Quit => "Quit".to_string(),
+            Signal::Change(context) => format!("Change({:?})", context),
+            Signal::Message(context, _) => format!("Message({:?})", context),
+            Signal::And(..) => "And".to_string(),
+            Signal::Spawn(_) => "Spawn".to_string(),
+            Signal::Cancel => "Cancel".to_string(),
+            Signal::Status(status) => format!("Status({:?})", status),
+            Signal::Finished(message) => format!("Finished({:?})", message),
+            Signal::Record(record) => format!("Record({:?})", record.outcome),
+            Signal::Navigate(path) => format!("Navigate({})", path.display()),
+            Signal::Refresh => "Refresh".to_string(),
+            Signal::Filter(filter) => format!("Filter({})", filter),
+            Signal::Reveal(path) => format!("Reveal({})", path.display()),
+            Signal::Entries(generation, batch) => {
+                format!("Entries({}, {} entries)", generation, batch.len())
+            }
+            Signal::Loaded(generation) => format!("Loaded({})", generation),
+            Signal::Vanished(generation, path) => {
+                format!("Vanished({}, {})", generation, path.display())
+            }
+            Signal::Git(generation, statuses) => {
+                format!("Git({}, {} statuses)", generation, statuses.len())
+            }
+            Signal::Suspend(command) => format!("Suspend({:?})", command.get_program()),
+            Signal::Clipboard(_) => "Clipboard".to_string(),
+            Signal::Tagged(tags) => format!("Tagged({})", tags.join(", ")),
+            Signal::TagAgain(path) => format!("TagAgain({})", path.display()),
+            Signal::Protect(path) => format!("Protect({})", path.display()),
+            Signal::DirsFirst => "DirsFirst".to_string(),
+            Signal::Recursive => "Recursive".to_string(),
+            Signal::Reindex => "Reindex".to_string(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct DirInfo {
+    /// Entries that pass the active filters, in display order.
     files: Vec<PathBuf>,
+    /// Every entry read from the directory.
+    entries: Vec<PathBuf>,
+    /// Metadata read alongside each entry, for filtering.
+    stats: HashMap<PathBuf, Stat>,
+    filter: Filter,
     path: String,
+    status: Option<String>,
+    config: DirConfig,
+    loading: bool,
+    git: HashMap<PathBuf, GitStatus>,
+    /// Bumped on every (re)load so batches from a stale loader are dropped.
+    generation: u64,
+    /// Entry to highlight once loading finishes.
+    reveal: Option<PathBuf>,
+    /// Entries with tags, for the `untagged` filter.
+    tagged: HashSet<PathBuf>,
+    /// Entries marked as protected from deletion.
+    protected: HashSet<PathBuf>,
+    /// Whether directories are listed before files, whatever the sort.
+    dirs_first: bool,
+    /// When set, every file up to this many levels down is listed instead
+    /// of the directory's own entries.
+    recursive: Option<usize>,
+    /// Paths listed instead of the directory's entries.
+    listed: Option<PathList>,
+    /// When the listing was last read in full.
+    loaded_at: Option<SystemTime>,
+    /// Whether an operation is running, which Esc cancels before anything
+    /// else.
+    busy: bool,
+}
+
+/// Paths read from `--from-file`, e.g. piped in from `find`.
+#[derive(Clone)]
+pub struct PathList {
+    /// Where the paths came from, shown in place of the directory.
+    source: String,
+    paths: Arc<Vec<PathBuf>>,
+}
+
+impl PathList {
+    /// Reads one path per line from `file`, or stdin for `-`, resolving
+    /// relative ones against `base`.
+    fn read(file: &Path, base: &Path) -> io::Result<PathList> {
+        let (source, contents) = match file == Path::new("-") {
+            true => ("stdin".to_string(), io::read_to_string(io::stdin())?),
+            false => (file.display().to_string(), fs::read_to_string(file)?),
+        };
+        let paths = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| base.join(line))
+            .collect();
+        Ok(PathList {
+            source,
+            paths: Arc::new(paths),
+        })
+    }
+}
+
+impl DirInfo {
+    fn new(path: String, generation: u64) -> DirInfo {
+        DirInfo {
+            files: vec![],
+            entries: vec![],
+            stats: HashMap::new(),
+            filter: Filter::default(),
+            config: DirConfig::load(Path::new(&path)),
+            path,
+            status: None,
+            loading: true,
+            git: HashMap::new(),
+            generation,
+            reveal: None,
+            tagged: HashSet::new(),
+            protected: HashSet::new(),
+            dirs_first: false,
+            recursive: None,
+            listed: None,
+            loaded_at: None,
+            busy: false,
+        }
+    }
+
+    /// Starts streaming the directory's entries in over `sender`.
+    fn load(&self, sender: mpsc::Sender<Signal>) {
+        let directory = PathBuf::from(&self.path);
+        let generation = self.generation;
+        let recursive = self.recursive;
+        if let Some(listed) = &self.listed {
+            let paths = listed.paths.clone();
+            thread::spawn(move || {
+                // Paths that no longer exist are left out.
+                for batch in paths.chunks(LOAD_BATCH) {
+                    let batch = batch
+                        .iter()
+                        .filter_map(|path| Stat::read(path).map(|stat| (path.clone(), Some(stat))))
+                        .collect();
+                    if sender.send(Signal::Entries(generation, batch)).is_err() {
+                        return;
+                    }
+                }
+                let _ = sender.send(Signal::Loaded(generation));
+            });
+            return;
+        }
+        thread::spawn(move || {
+            let entries = match fs::read_dir(&directory) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    let _ = sender.send(Signal::Vanished(generation, directory));
+                    return;
+                }
+                Err(e) => {
+                    let _ = sender.send(Signal::Status(format!("{}: {}", directory.display(), e)));
+                    let _ = sender.send(Signal::Loaded(generation));
+                    return;
+                }
+            };
+            let mut batch = Vec::with_capacity(LOAD_BATCH);
+            let mut pending = vec![(entries, 1)];
+            while let Some((entries, depth)) = pending.pop() {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if let Some(max_depth) = recursive {
+                        // Flattened listings show files only, without
+                        // following symlinked directories.
+                        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                            if let Some(entries) =
+                                fs::read_dir(&path).ok().filter(|_| depth < max_depth)
+                            {
+                                pending.push((entries, depth + 1));
+                            }
+                            continue;
+                        }
+                    }
+                    let stat = Stat::read(&path);
+                    batch.push((path, stat));
+                    if batch.len() == LOAD_BATCH {
+                        let batch = std::mem::replace(&mut batch, Vec::with_capacity(LOAD_BATCH));
+                        if sender.send(Signal::Entries(generation, batch)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            let _ = sender.send(Signal::Entries(generation, batch));
+            let _ = sender.send(Signal::Loaded(generation));
+            let _ = sender.send(Signal::Git(generation, git::statuses(&directory)));
+        });
+    }
+
+    /// Re-reads the listing, picking up changes to the directory's `.tidy` file.
+    fn refresh(&mut self, sender: mpsc::Sender<Signal>) {
+        self.config = DirConfig::load(Path::new(&self.path));
+        self.files.clear();
+        self.entries.clear();
+        self.stats.clear();
+        self.loading = true;
+        self.generation += 1;
+        self.load(sender);
+    }
+
+    fn extend(&mut self, batch: Vec<(PathBuf, Option<Stat>)>) {
+        let now = SystemTime::now();
+        for (file, stat) in batch {
+            if let Some(stat) = stat {
+                self.stats.insert(file.clone(), stat);
+            }
+            if self.passes(&file, now) {
+                self.files.push(file.clone());
+            }
+            self.entries.push(file);
+        }
+    }
+
+    fn passes(&self, file: &Path, now: SystemTime) -> bool {
+        !self.config.hides(file)
+            && self.filter.matches(self.stats.get(file), now)
+            && self.filter.matches_name(file)
+            && !(self.filter.untagged && self.tagged.contains(file))
+    }
+
+    fn finish_loading(&mut self) {
+        if let Some(sort) = self.config.sort {
+            sort.sort(&mut self.files, &self.stats);
+        }
+        if self.dirs_first {
+            // Stable, so the sort above still orders each group.
+            let stats = &self.stats;
+            self.files
+                .sort_by_key(|file| stats.get(file).is_none_or(|stat| stat.size.is_some()));
+        }
+        self.loading = false;
+    }
+
+    /// Re-filters the entries already read against `filter`.
+    fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        let now = SystemTime::now();
+        self.files = self
+            .entries
+            .iter()
+            .filter(|file| self.passes(file, now))
+            .cloned()
+            .collect();
+        if !self.loading {
+            self.finish_loading();
+        }
+    }
+
+    /// Replaces which entries count as tagged, re-filtering if that matters.
+    fn set_tagged(&mut self, tagged: HashSet<PathBuf>) {
+        self.tagged = tagged;
+        if self.filter.untagged {
+            self.set_filter(self.filter.clone());
+        }
+    }
+
+    /// Number of entries the active filters leave out of the listing.
+    fn hidden(&self) -> usize {
+        self.entries.len() - self.files.len()
+    }
 }
 
 impl State {
     fn handle_key(&mut self, event: KeyEvent) {
-        let signal = self
-            .ctx_map
-            .get_mut(&self.context)
-            .expect("Context not found.")
-            .handle_key(event, self.info.clone());
+        // Raw mode turns Ctrl-C into a key, so it quits from every context.
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            return self.channel.send(Signal::Quit);
+        }
+        self.info.busy = self.worker.is_some();
+        let signal = match self.ctx_map.get_mut(&self.context) {
+            Some(ctx) => ctx.handle_key(event, &self.info),
+            None => return self.missing(self.context),
+        };
 
         if let Some(signal) = signal {
             self.channel.send(signal);
         }
     }
 
-    fn render(&mut self, rect: &mut Frame<CrosstermBackend<Stdout>>) {
-        self.ctx_map
-            .get_mut(&self.context)
-            .expect("Context not found.")
-            .render(rect, self.info.clone());
+    /// Delivers `msg`, or holds on to it until `context` is registered.
+    fn send(&mut self, context: ContextId, msg: Msg) {
+        match self.ctx_map.get_mut(&context) {
+            Some(ctx) => ctx.send(msg),
+            None => {
+                let queue = self.pending.entry(context).or_default();
+                if queue.len() == PENDING_LIMIT {
+                    queue.remove(0);
+                }
+                queue.push(msg);
+                self.missing(context);
+            }
+        }
+    }
+
+    /// Adds a context, handing it any messages that arrived before it did.
+    fn register(&mut self, mut ctx: Box<dyn Ctx>) {
+        for msg in self.pending.remove(&ctx.id()).unwrap_or_default() {
+            ctx.send(msg);
+        }
+        self.ctx_map.insert(ctx.id(), ctx);
+    }
+
+    /// Switches to `context`, staying put if it was never registered.
+    fn change(&mut self, context: ContextId) {
+        match self.ctx_map.contains_key(&context) {
+            true => {
+                log::debug!("switching from {:?} to {:?}", self.context, context);
+                self.context = context;
+            }
+            false => self.missing(context),
+        }
+    }
+
+    /// Entries of the current listing with tags. A failed lookup counts
+    /// none, so the `untagged` filter shows everything rather than nothing.
+    fn tagged(&self) -> HashSet<PathBuf> {
+        match self.info.recursive.is_some() || self.info.listed.is_some() {
+            // Entries from all over, so they're looked up one by one.
+            true => self
+                .info
+                .entries
+                .iter()
+                .filter(|file| {
+                    self.storage
+                        .tags_for(file)
+                        .is_ok_and(|tags| !tags.is_empty())
+                })
+                .cloned()
+                .collect(),
+            false => self
+                .storage
+                .tagged_in(Path::new(&self.info.path))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Protected entries of the current directory, or none if the lookup
+    /// fails; deleting checks the database itself.
+    fn protected(&self) -> HashSet<PathBuf> {
+        self.storage
+            .protected_in(Path::new(&self.info.path))
+            .map(|paths| paths.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Picks up tag changes, so newly tagged files leave an `untagged` listing.
+    fn retag(&mut self) {
+        let tagged = self.tagged();
+        self.info.set_tagged(tagged);
+        self.send(ContextId::Main, Msg::Refreshed(self.info.files.len()));
     }
 
-    fn new(opts: Opts) -> Result<Self> {
+    /// Reports a signal aimed at an unregistered context instead of crashing.
+    fn missing(&mut self, context: ContextId) {
+        let message = format!("No {:?} context registered", context);
+        log::warn!("{}", message);
+        if let Some(log) = self.ctx_map.get_mut(&ContextId::Log) {
+            log.send(Msg::Log(message.clone()));
+        }
+        self.info.status = Some(message);
+    }
+
+    /// Draws the current context, returning whether it raised a signal.
+    fn render(&mut self, rect: &mut Frame<CrosstermBackend<Stderr>>) -> bool {
+        let size = rect.size();
+        if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+            let message = Paragraph::new(format!(
+                "Terminal too small ({}x{}, need {}x{})",
+                size.width, size.height, MIN_WIDTH, MIN_HEIGHT
+            ))
+            .wrap(Wrap { trim: true });
+            rect.render_widget(message, size);
+            return false;
+        }
+        let ctx = match self.ctx_map.get_mut(&self.context) {
+            Some(ctx) => ctx,
+            None => {
+                self.missing(self.context);
+                return false;
+            }
+        };
+        ctx.render(rect, &self.info);
+        if let Some(timings) = &self.timings {
+            let text = format!(
+                " render {:.1}ms, events {:.1}ms ",
+                timings.render.as_secs_f64() * 1000.0,
+                timings.events.as_secs_f64() * 1000.0
+            );
+            let width = (text.len() as u16).min(size.width);
+            let corner = Rect {
+                x: size.width - width,
+                y: 0,
+                width,
+                height: 1,
+            };
+            rect.render_widget(
+                Paragraph::new(text).style(self.config.theme.highlight()),
+                corner,
+            );
+        }
+        match ctx.take_signal() {
+            Some(signal) => {
+                self.channel.send(signal);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn new(opts: Opts) -> Result<Self, Box<dyn Error>> {
+        let debug = opts.debug;
+        let mut config = Config::load(opts.config);
+        config.read_only = opts.read_only;
+        if let Some(millis) = opts.tick_rate_ms {
+            config.tick_rate = config::tick_rate(millis);
+        }
+        if let Some(size) = opts.max_read_size {
+            config.max_read_size = size;
+        }
+        // https://no-color.org: any non-empty value turns colour off.
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        config.theme.color = !(opts.no_color || no_color);
+        let config = Rc::new(config);
+
+        let database = Database::choose(opts.memory, opts.db)?;
+        let storage: Rc<dyn Storage> = Rc::new(database.open()?);
+
+        // Whatever the command line asks for wins over the last session.
+        // A database that has never been opened before means a new user.
+        let first_run = matches!(storage.setting(ONBOARDED), Ok(None));
+        if first_run {
+            // Failing to save this only means the hint shows again next time.
+            let _ = storage.set_setting(ONBOARDED, "true");
+        }
+
+        let fresh = opts.fresh;
+        let session = |key| match fresh {
+            true => None,
+            false => storage.setting(key).ok().flatten(),
+        };
+        // A quoted `~` reaches us unexpanded; relative paths resolve against
+        // the working directory when canonicalized.
         let directory = opts
             .directory
-            .or(std::env::current_dir().ok())
-            .unwrap()
-            .canonicalize()
-            .unwrap()
+            .map(|directory| match directory.to_str() {
+                Some(directory) => paths::expand_tilde(directory),
+                None => directory,
+            })
+            .or_else(|| {
+                session(SESSION_DIRECTORY)
+                    .map(PathBuf::from)
+                    .filter(|path| path.is_dir())
+            })
+            .map_or_else(std::env::current_dir, Ok)?;
+        let directory = paths::check_dir(&directory)
+            .map_err(|e| format!("{}: {}", directory.display(), e))?
             .into_os_string()
             .into_string()
-            .unwrap();
+            .map_err(|path| format!("Not valid unicode: {:?}", path))?;
 
         let mut file_list_state = ListState::default();
         file_list_state.select(Some(0));
@@ -118,123 +703,490 @@ impl State {
         let main_ctx = MainContext {
             file_list_state,
             selection: vec![],
+            yanked: vec![],
+            pending_delete: None,
+            pending_paste: None,
+            pending_key: None,
+            vanished: None,
+            signal: None,
+            storage: storage.clone(),
+            view: session(SESSION_VIEW)
+                .and_then(|view| View::parse(&view))
+                .unwrap_or(config.view),
+            grid_columns: 1,
+            row_offset: 0,
+            columns: view::Columns::load(),
+            expanded: None,
+            show_preview: config.preview,
+            type_ahead: None,
+            long: false,
+            show_info: config.density == Density::Normal,
+            show_commands: true,
+            onboarding: first_run,
+            focus: Panel::Files,
+            preview_scroll: 0,
+            info_scroll: 0,
+            preview: None,
+            lookups: Default::default(),
+            path_display: PathDisplay::Name,
+            start_dir: PathBuf::from(&directory),
+            config: config.clone(),
         };
 
-        let tag_ctx = TaggingContext {
-            tag_input: vec![],
-            file_path: None,
-        };
+        let tag_ctx = TaggingContext::new(storage.clone(), config.clone());
 
-        let mut ctx_map: HashMap<TypeId, Box<dyn Ctx>> = HashMap::new();
-        ctx_map.insert(TypeId::of::<MainContext>(), Box::new(main_ctx));
-        ctx_map.insert(TypeId::of::<TaggingContext>(), Box::new(tag_ctx));
+        let contexts: Vec<Box<dyn Ctx>> = vec![
+            Box::new(main_ctx),
+            Box::new(tag_ctx),
+            Box::new(GotoContext {
+                input: String::new(),
+                candidates: vec![],
+                error: None,
+                config: config.clone(),
+            }),
+            Box::new(SearchContext::new(config.clone())),
+            Box::new(LogContext::new(config.clone())),
+            Box::new(FilterContext::new(config.clone())),
+            Box::new(SummaryContext::new(config.clone())),
+            Box::new(OpenWithContext::new(config.clone())),
+            Box::new(RenameContext::new(storage.clone(), config.clone())),
+            Box::new(NoteContext::new(storage.clone(), config.clone())),
+        ];
+        let mut info = DirInfo::new(directory, 0);
+        if let Some(file) = opts.from_file {
+            let base = std::env::current_dir()?;
+            info.listed = Some(
+                PathList::read(&file, &base)
+                    .map_err(|e| format!("--from-file {}: {}", file.display(), e))?,
+            );
+        }
+        info.dirs_first = match storage.setting(DIRS_FIRST) {
+            Ok(Some(value)) => value == "true",
+            _ => config.dirs_first,
+        };
+        match opts.filter {
+            Some(filter) => {
+                info.filter = Filter::parse(&filter).map_err(|e| format!("--filter: {}", e))?
+            }
+            // A filter that no longer parses is dropped rather than refusing to start.
+            None => {
+                if let Some(filter) = session(SESSION_FILTER).and_then(|f| Filter::parse(&f).ok()) {
+                    info.filter = filter;
+                }
+            }
+        }
+        let channel = Channel::new();
+        info.load(channel.sender.clone());
 
-        let files: Vec<PathBuf> = fs::read_dir(directory.clone())
-            .map(|dir: ReadDir| {
-                dir.map(|res: Result<DirEntry, Error>| {
-                    res.map(|entry: DirEntry| entry.path().canonicalize().unwrap())
-                })
-            })
-            .unwrap()
-            .flatten()
-            .collect();
+        let mut state = State {
+            info,
+            channel,
+            ctx_map: HashMap::new(),
+            pending: HashMap::new(),
+            context: ContextId::Main,
+            worker: None,
+            config,
+            database,
+            storage,
+            last_tags: vec![],
+            timings: debug.then(Timings::default),
+        };
+        for ctx in contexts {
+            state.register(ctx);
+        }
+        Ok(state)
+    }
 
-        Ok(State {
-            info: DirInfo {
-                path: directory,
-                files,
-            },
-            channel: Channel::new(),
-            ctx_map,
-            context: TypeId::of::<MainContext>(),
-        })
+    /// Remembers the directory, filter and view for the next launch.
+    fn save_session(&self) -> Result<(), Box<dyn Error>> {
+        self.storage
+            .set_setting(SESSION_DIRECTORY, &self.info.path)?;
+        self.storage
+            .set_setting(SESSION_FILTER, &self.info.filter.to_string())?;
+        if let Some(view) = self
+            .ctx_map
+            .get(&ContextId::Main)
+            .and_then(|ctx| ctx.view())
+        {
+            self.storage.set_setting(SESSION_VIEW, view.name())?;
+        }
+        Ok(())
     }
 }
 
+/// Smallest terminal the fixed-size panels of every context fit in.
+const MIN_WIDTH: u16 = 40;
+const MIN_HEIGHT: u16 = 16;
+/// Messages kept for a context that isn't registered; older ones are dropped.
+const PENDING_LIMIT: usize = 64;
+/// Setting that remembers the last dirs-first toggle, overriding the config.
+const DIRS_FIRST: &str = "dirs_first";
+/// Setting marking that the first-run hint has been shown.
+const ONBOARDED: &str = "onboarded";
+/// Settings restoring where the last session left off, unless `--fresh`.
+const SESSION_DIRECTORY: &str = "session_directory";
+const SESSION_FILTER: &str = "session_filter";
+const SESSION_VIEW: &str = "session_view";
+/// Number of entries read before they're handed to the UI.
+const LOAD_BATCH: usize = 256;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "tidy", about = "A tui file explorer in rust")]
 struct Opts {
     #[structopt(parse(from_os_str))]
     directory: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    subcommand: Option<cli::Subcommand>,
+
+    /// Disable every operation that changes files or tags
+    #[structopt(long)]
+    read_only: bool,
+
+    /// Print the highlighted entry (or the current directory) on exit, e.g. for `cd "$(tidy)"`
+    #[structopt(long)]
+    print_selection: bool,
+
+    /// Config file to read instead of the one in the platform config directory
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Tag database to use instead of the one in the platform data directory
+    #[structopt(long, parse(from_os_str))]
+    db: Option<PathBuf>,
+
+    /// Keep tags in memory only; nothing is written and they're gone on exit
+    #[structopt(long, visible_alias = "no-persist", conflicts_with = "db")]
+    memory: bool,
+
+    /// Draw without colours, as when NO_COLOR is set
+    #[structopt(long)]
+    no_color: bool,
+
+    /// Milliseconds between redraws while idle (16 to 5000)
+    #[structopt(long)]
+    tick_rate_ms: Option<u64>,
+
+    /// Largest file read whole, e.g. to hash it, like '100M'
+    #[structopt(long, parse(try_from_str = filter::parse_size))]
+    max_read_size: Option<u64>,
+
+    /// Start with the listing filtered, e.g. '*.rs' or 'size>100M'
+    #[structopt(long)]
+    filter: Option<String>,
+
+    /// List the paths in this file, one per line, instead of a directory; `-` reads stdin
+    #[structopt(long, parse(from_os_str))]
+    from_file: Option<PathBuf>,
+
+    /// Show how long each frame takes to draw and handle input in a corner
+    #[structopt(long)]
+    debug: bool,
+
+    /// Write a debug log to this file; RUST_LOG sets the level
+    #[structopt(long, parse(from_os_str))]
+    log: Option<PathBuf>,
+
+    /// Start afresh instead of in the last session's directory, filter and view
+    #[structopt(long)]
+    fresh: bool,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let stdout = io::stdout();
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+/// The TUI draws to stderr so stdout stays free for `--print-selection`.
+type Term = Terminal<CrosstermBackend<Stderr>>;
+
+fn setup_terminal() -> crossterm::Result<Term> {
+    terminal::enable_raw_mode()?;
+    let mut stderr = io::stderr();
+    execute!(stderr, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stderr))?;
     terminal.clear()?;
+    Ok(terminal)
+}
 
+fn restore_terminal(terminal: &mut Term) -> crossterm::Result<()> {
+    terminal::disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+/// Runs `command` with the terminal restored to its normal state.
+fn suspend(terminal: &mut Term, command: &mut process::Command) -> Result<String, Box<dyn Error>> {
+    restore_terminal(terminal)?;
+    let status = command.status();
+    terminal::enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal.clear()?;
+    Ok(match status {
+        Ok(status) if status.success() => String::new(),
+        Ok(status) => format!("{:?} exited with {}", command.get_program(), status),
+        Err(e) => format!("{:?}: {}", command.get_program(), e),
+    })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts = Opts::from_args();
+    if let Err(e) = logging::init(opts.log.clone()) {
+        eprintln!("Couldn't open the log: {}", e);
+    }
+    let print_selection = opts.print_selection;
 
-    let mut state = State::new(opts)?;
-    let conn = Connection::open("tidy.db")?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS dirs (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL UNIQUE
-            )",
-        [],
-    )?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS files (
-                id INTEGER PRIMARY KEY,
-                path TEXT NOT NULL,
-                path_id INTEGER NOT NULL REFERENCES dirs(id)
-            )",
-        [],
-    )?;
-    conn.execute(
-        "INSERT OR IGNORE INTO dirs (path) VALUES (?)",
-        [state.info.path.clone()],
-    )?;
-
-    let mut select = conn.prepare("SELECT id FROM dirs WHERE path = ?")?;
-
-    if let Some(Ok(name)) = select
-        .query_map::<u32, _, _>([state.info.path.clone()], |row| row.get(0))?
-        .next()
-    {
-        let mut stmt = conn.prepare("INSERT OR IGNORE INTO files (path, path_id) VALUES (?, ?)")?;
-        for path in &state.info.files {
-            stmt.insert(params![
-                path.clone()
-                    .into_os_string()
-                    .into_string()
-                    .expect("Could not convert to string"),
-                name
-            ])?;
+    if let Some(subcommand) = opts.subcommand {
+        if opts.read_only && subcommand.mutates() {
+            return Err("Read-only mode".into());
         }
+        let storage = Database::choose(opts.memory, opts.db)?.open()?;
+        return cli::run(subcommand, &storage);
+    }
+
+    let mut state = State::new(opts)?;
+
+    let mut terminal = setup_terminal()?;
+    let result = run(&mut terminal, &mut state);
+    restore_terminal(&mut terminal)?;
+    if let Err(e) = &result {
+        log::error!("{}", e);
+    }
+    result?;
+    if let Err(e) = state.save_session() {
+        eprintln!("Couldn't save the session: {}", e);
     }
 
+    if print_selection {
+        let picked = state
+            .ctx_map
+            .get(&ContextId::Main)
+            .and_then(|ctx| ctx.current_file(&state.info))
+            .unwrap_or_else(|| PathBuf::from(&state.info.path));
+        println!("{}", picked.display());
+    }
+    Ok(())
+}
+
+fn run(terminal: &mut Term, state: &mut State) -> Result<(), Box<dyn Error>> {
     loop {
         // UI Loop
+        let mut raised = false;
+        let started = Instant::now();
         terminal.draw(|rect| {
-            state.render(rect);
+            raised = state.render(rect);
         })?;
-        // Event Loop, Blocking
-        match read().unwrap() {
-            Event::Key(event) => state.handle_key(event),
-            Event::Mouse(_event) => {}
-            Event::Resize(_width, _height) => {}
-        };
+        if let Some(timings) = &mut state.timings {
+            timings.render = started.elapsed();
+        }
+        // Event Loop, polled so worker progress is drawn between keypresses.
+        // Everything already queued is handled before the next draw, so held
+        // keys don't build up a backlog of frames. A signal raised while
+        // drawing is settled straight away instead of after a whole tick.
+        let mut ready = poll(match raised {
+            true => Duration::from_secs(0),
+            false => state.config.tick_rate,
+        })?;
+        // Time spent handling, leaving out the wait for input.
+        let mut busy = Duration::from_secs(0);
+        while ready {
+            let started = Instant::now();
+            match read()? {
+                Event::Key(event) => state.handle_key(event),
+                Event::Mouse(_event) => {}
+                Event::Resize(_width, _height) => {}
+            };
+            if handle_signals(terminal, state)? {
+                return Ok(());
+            }
+            busy += started.elapsed();
+            ready = poll(Duration::from_secs(0))?;
+        }
+        let started = Instant::now();
+        if handle_signals(terminal, state)? {
+            return Ok(());
+        }
+        if let Some(timings) = &mut state.timings {
+            timings.events = busy + started.elapsed();
+        }
+    }
+}
 
-        for signal in state.channel.receiver.try_iter() {
-            match signal {
-                Signal::Quit => {
-                    terminal.clear()?;
-                    return Ok(());
+/// Handles queued signals until none are left, including any queued while
+/// handling them, returning whether tidy should quit.
+fn handle_signals(terminal: &mut Term, state: &mut State) -> Result<bool, Box<dyn Error>> {
+    while let Ok(signal) = state.channel.receiver.try_recv() {
+        match signal {
+            // Loading a big directory sends a lot of these.
+            Signal::Entries(..) | Signal::Git(..) => log::trace!("{}", signal.describe()),
+            _ => log::debug!("{}", signal.describe()),
+        }
+        match signal {
+            Signal::Quit => return Ok(true),
+            Signal::Change(context) => state.change(context),
+            Signal::Message(context, msg) => state.send(context, msg),
+            Signal::Spawn(_) if state.config.read_only => {
+                state.info.status = Some("Read-only mode".to_string());
+            }
+            Signal::Spawn(op) => {
+                if state.worker.is_some() {
+                    state.info.status = Some("Another operation is running.".to_string());
+                } else {
+                    state.worker = Some(Worker::spawn(op, state.channel.sender.clone()));
+                }
+            }
+            Signal::Cancel => {
+                if let Some(worker) = &state.worker {
+                    worker.cancel();
                 }
-                Signal::Change(context) => {
-                    state.context = context;
+            }
+            Signal::Status(status) => state.info.status = Some(status),
+            Signal::Record(record) => {
+                if let Err(e) = state
+                    .storage
+                    .record_operation(&record.directory, &record.outcome)
+                {
+                    log::warn!("Couldn't record {:?}: {}", record.outcome, e);
+                }
+            }
+            Signal::Finished(status) => {
+                state.worker = None;
+                state.send(ContextId::Log, Msg::Log(status.clone()));
+                state.info.status = Some(status);
+                state.info.refresh(state.channel.sender.clone());
+            }
+            Signal::Entries(generation, files) if generation == state.info.generation => {
+                state.info.extend(files);
+            }
+            Signal::Loaded(generation) if generation == state.info.generation => {
+                let tagged = state.tagged();
+                state.info.set_tagged(tagged);
+                state.info.protected = state.protected();
+                state.info.finish_loading();
+                state.info.loaded_at = Some(SystemTime::now());
+                state.send(ContextId::Main, Msg::Refreshed(state.info.files.len()));
+                let info = &mut state.info;
+                if let Some(index) = info
+                    .reveal
+                    .take()
+                    .and_then(|path| info.files.iter().position(|file| *file == path))
+                {
+                    state.send(ContextId::Main, Msg::Select(index));
+                }
+                // Only the directory tidy was started in gets indexed.
+                if generation == 0 {
+                    db::spawn_index(
+                        state.database.clone(),
+                        state.info.path.clone(),
+                        state.info.files.clone(),
+                        state.channel.sender.clone(),
+                    );
+                }
+            }
+            Signal::Git(generation, statuses) if generation == state.info.generation => {
+                state.info.git = statuses;
+            }
+            Signal::Vanished(generation, directory) if generation == state.info.generation => {
+                let status = format!("{} was removed", directory.display());
+                match paths::fallback_dir(&directory) {
+                    Some(fallback) => {
+                        let _ = state.channel.sender.send(Signal::Navigate(fallback));
+                        let _ = state.channel.sender.send(Signal::Status(status));
+                    }
+                    None => {
+                        state.info.loading = false;
+                        state.info.status = Some(status);
+                    }
+                }
+            }
+            Signal::Entries(..) | Signal::Loaded(..) | Signal::Git(..) | Signal::Vanished(..) => {}
+            Signal::Navigate(path) => match paths::check_dir(&path).and_then(|path| {
+                path.into_os_string()
+                    .into_string()
+                    .map_err(|path| format!("Not valid unicode: {:?}", path))
+            }) {
+                Ok(path) => {
+                    let filter = std::mem::take(&mut state.info.filter);
+                    let dirs_first = state.info.dirs_first;
+                    let recursive = state.info.recursive;
+                    state.info = DirInfo::new(path, state.info.generation + 1);
+                    state.info.filter = filter;
+                    state.info.dirs_first = dirs_first;
+                    state.info.recursive = recursive;
+                    state.info.load(state.channel.sender.clone());
+                    state.send(ContextId::Main, Msg::Navigated);
                 }
-                Signal::Message(context, msg) => state
-                    .ctx_map
-                    .get_mut(&context)
-                    .expect("Context not found.")
-                    .send(msg),
-                _ => {}
+                Err(e) => state.info.status = Some(e),
+            },
+            Signal::Reveal(path) => state.info.reveal = Some(path),
+            Signal::Refresh => state.info.refresh(state.channel.sender.clone()),
+            Signal::Filter(filter) => {
+                state.info.tagged = state.tagged();
+                state.info.set_filter(filter);
+                state.send(ContextId::Main, Msg::Refreshed(state.info.files.len()));
+            }
+            Signal::Suspend(mut command) => {
+                let status = suspend(terminal, &mut command)?;
+                state.info.status = Some(status).filter(|status| !status.is_empty());
+                state.info.refresh(state.channel.sender.clone());
+            }
+            Signal::Tagged(tags) => {
+                state.last_tags = tags;
+                state.retag();
+            }
+            Signal::TagAgain(_) if state.config.read_only => {
+                state.info.status = Some("Read-only mode".to_string());
+            }
+            Signal::TagAgain(_) if state.last_tags.is_empty() => {
+                state.info.status = Some("Nothing tagged yet".to_string());
+            }
+            Signal::TagAgain(path) => {
+                let tags = state.last_tags.join(", ");
+                let message = match state.storage.add_tags(&path, &state.last_tags) {
+                    Ok(()) => format!("Tagged {} with {}", path.display(), tags),
+                    Err(e) => format!("Tagging {} failed: {}", path.display(), e),
+                };
+                state.send(ContextId::Log, Msg::Log(message.clone()));
+                state.info.status = Some(message);
+                state.retag();
+            }
+            Signal::Recursive if state.info.listed.is_some() => {
+                state.info.status = Some("A path list can't be listed recursively".to_string());
+            }
+            Signal::Recursive => {
+                state.info.recursive = match state.info.recursive {
+                    Some(_) => None,
+                    None => Some(state.config.max_depth),
+                };
+                state.info.refresh(state.channel.sender.clone());
+            }
+            Signal::DirsFirst => {
+                let dirs_first = !state.info.dirs_first;
+                state.info.dirs_first = dirs_first;
+                state.info.set_filter(state.info.filter.clone());
+                state.send(ContextId::Main, Msg::Refreshed(state.info.files.len()));
+                if let Err(e) = state
+                    .storage
+                    .set_setting(DIRS_FIRST, &dirs_first.to_string())
+                {
+                    state.info.status = Some(format!("Couldn't save the sort order: {}", e));
+                }
+            }
+            Signal::Reindex => db::spawn_reindex(
+                state.database.clone(),
+                PathBuf::from(&state.info.path),
+                state.channel.sender.clone(),
+            ),
+            Signal::Protect(path) => {
+                let protect = !state.info.protected.contains(&path);
+                let message = match state.storage.set_protected(&path, protect) {
+                    Ok(()) if protect => format!("Protected {}", path.display()),
+                    Ok(()) => format!("Unprotected {}", path.display()),
+                    Err(e) => format!("Protecting {} failed: {}", path.display(), e),
+                };
+                state.send(ContextId::Log, Msg::Log(message.clone()));
+                state.info.status = Some(message);
+                state.info.protected = state.protected();
             }
+            Signal::Clipboard(text) => clipboard::copy(terminal.backend_mut(), &text)?,
+            Signal::And(..) => {}
         }
     }
+    Ok(false)
 }
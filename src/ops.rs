@@ -0,0 +1,483 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{ctx::ContextId, paths, Msg, Signal};
+
+pub enum Operation {
+    Copy(Vec<CopyItem>),
+    Delete(Vec<PathBuf>),
+    /// A command configured by the user, reporting how it went.
+    Run(process::Command),
+}
+
+/// One entry of a paste, with how a name clash was settled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyItem {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Replace whatever is at `to` instead of refusing to.
+    pub overwrite: bool,
+}
+
+impl Operation {
+    fn describe(&self) -> String {
+        match self {
+            Operation::Copy(items) if items.len() == 1 => {
+                format!("Copying {}", items[0].from.display())
+            }
+            Operation::Copy(items) => format!("Copying {} entries", items.len()),
+            Operation::Delete(paths) if paths.len() == 1 => {
+                format!("Deleting {}", paths[0].display())
+            }
+            Operation::Delete(paths) => format!("Deleting {} entries", paths.len()),
+            Operation::Run(command) => {
+                format!("Running {}", command.get_program().to_string_lossy())
+            }
+        }
+    }
+
+    /// The directory the operation is filed under in the history.
+    fn directory(&self) -> Option<PathBuf> {
+        match self {
+            Operation::Copy(items) => items.first().and_then(|item| item.to.parent()),
+            Operation::Delete(paths) => paths.first().and_then(|path| path.parent()),
+            Operation::Run(command) => command.get_current_dir(),
+        }
+        .map(Path::to_path_buf)
+    }
+
+    /// What was done, in the past tense, like "deleted 40 files". `None`
+    /// when nothing was.
+    fn outcome(&self, summary: &Summary, succeeded: bool) -> Option<String> {
+        let done = match self {
+            Operation::Copy(_) => format!("copied {} entries", summary.succeeded),
+            Operation::Delete(_) => format!("deleted {} files", summary.succeeded),
+            Operation::Run(command) if succeeded => {
+                return Some(format!("ran {}", command.get_program().to_string_lossy()))
+            }
+            Operation::Run(_) => return None,
+        };
+        match (summary.succeeded, summary.failed.len()) {
+            (0, 0) => None,
+            (_, 0) => Some(done),
+            (_, failed) => Some(format!("{}, {} failed", done, failed)),
+        }
+    }
+}
+
+/// A finished operation, as kept in the history of the directory it was in.
+#[derive(Debug)]
+pub struct Record {
+    pub directory: PathBuf,
+    pub outcome: String,
+}
+
+/// How each item of an operation that carries on past failures went.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub description: String,
+    pub succeeded: usize,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Summary {
+    fn fail(&mut self, path: &Path, e: io::Error) {
+        self.failed.push((path.to_path_buf(), e.to_string()));
+    }
+
+    pub fn headline(&self) -> String {
+        format!(
+            "{}: {} succeeded, {} failed",
+            self.description,
+            self.succeeded,
+            self.failed.len()
+        )
+    }
+}
+
+/// Handle to an operation running on a worker thread.
+pub struct Worker {
+    cancel: Arc<AtomicBool>,
+}
+
+impl Worker {
+    pub fn spawn(mut op: Operation, sender: mpsc::Sender<Signal>) -> Worker {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        thread::spawn(move || {
+            let description = op.describe();
+            let mut summary = Summary {
+                description: description.clone(),
+                ..Summary::default()
+            };
+            let mut count = 0;
+            let mut progress = || {
+                count += 1;
+                let _ = sender.send(Signal::Status(format!(
+                    "{}: {} files (Esc to cancel)",
+                    description, count
+                )));
+            };
+            let mut output = None;
+            let result = match &mut op {
+                Operation::Copy(items) => {
+                    let mut result = Ok(());
+                    for item in items {
+                        match copy_item(item, &flag, &mut progress) {
+                            Ok(()) => summary.succeeded += 1,
+                            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                                result = Err(e);
+                                break;
+                            }
+                            Err(e) => summary.fail(&item.from, e),
+                        }
+                    }
+                    result
+                }
+                Operation::Delete(paths) => paths.iter().try_for_each(|path| {
+                    remove_recursive(path, &flag, &mut progress, &mut summary)
+                }),
+                Operation::Run(command) => run(command, &flag).map(|last_line| output = last_line),
+            };
+            let outcome = op.outcome(&summary, result.is_ok());
+            if let (Some(directory), Some(outcome)) = (op.directory(), outcome) {
+                let _ = sender.send(Signal::Record(Record { directory, outcome }));
+            }
+            let message = match result {
+                Ok(()) if !summary.failed.is_empty() => {
+                    let headline = summary.headline();
+                    let _ = sender.send(Signal::Message(ContextId::Summary, Msg::Summary(summary)));
+                    let _ = sender.send(Signal::Change(ContextId::Summary));
+                    headline
+                }
+                Ok(()) => match output {
+                    Some(line) => format!("{}: {}", description, line),
+                    None => format!("{}: done", description),
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    format!("{}: cancelled", description)
+                }
+                Err(e) => format!("{}: {}", description, e),
+            };
+            let _ = sender.send(Signal::Finished(message));
+        });
+        Worker { cancel }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// How often a running command is checked on for a cancel.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs `command` to the end, giving the last line it printed. Failing
+/// with a status counts as an error, described by its last error line.
+/// Cancelling kills it.
+fn run(command: &mut process::Command, cancel: &AtomicBool) -> io::Result<Option<String>> {
+    let mut child = command
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()?;
+    // Read as it runs, so a chatty command can't fill a pipe and stall.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = loop {
+        if let Err(e) = check(cancel) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(e);
+        }
+        match child.try_wait()? {
+            Some(status) => break status,
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    match status.success() {
+        true => Ok(stdout.or(stderr)),
+        false => Err(io::Error::other(match stderr.or(stdout) {
+            Some(line) => format!("{} ({})", status, line),
+            None => status.to_string(),
+        })),
+    }
+}
+
+/// Reads a child's pipe to the end on its own thread, for its last line.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Option<String>> {
+    thread::spawn(move || {
+        let mut bytes = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        last_line(&bytes)
+    })
+}
+
+fn last_line(bytes: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+fn check(cancel: &AtomicBool) -> io::Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Copies one pasted entry. An overwrite copies beside the destination
+/// first and only swaps it in once that's done, so a failed or cancelled
+/// paste leaves the destination as it was.
+fn copy_item(item: &CopyItem, cancel: &AtomicBool, progress: &mut dyn FnMut()) -> io::Result<()> {
+    if nested(&item.from, &item.to)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "can't paste an entry into or over itself",
+        ));
+    }
+    let exists = fs::symlink_metadata(&item.to).is_ok();
+    if exists && !item.overwrite {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "destination already exists",
+        ));
+    }
+    let target = match exists {
+        true => paths::free_name(&item.to),
+        false => item.to.clone(),
+    };
+    if let Err(e) = copy_recursive(&item.from, &target, cancel, progress) {
+        // Don't leave a half-copied tree behind.
+        discard(&target);
+        return Err(e);
+    }
+    if exists {
+        replace(&target, &item.to)?;
+    }
+    Ok(())
+}
+
+/// Whether `from` and `to` are the same entry or one is inside the other.
+fn nested(from: &Path, to: &Path) -> io::Result<bool> {
+    let from = fs::canonicalize(from)?;
+    // The destination may not exist yet, but its directory does.
+    let to = match (to.parent(), to.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent)?.join(name),
+        _ => fs::canonicalize(to)?,
+    };
+    Ok(from.starts_with(&to) || to.starts_with(&from))
+}
+
+/// Moves the finished copy at `copy` over `to`, putting `to` back if that
+/// fails.
+fn replace(copy: &Path, to: &Path) -> io::Result<()> {
+    let old = paths::free_name(to);
+    if let Err(e) = fs::rename(to, &old) {
+        discard(copy);
+        return Err(io::Error::other(format!("couldn't replace it: {}", e)));
+    }
+    if let Err(e) = fs::rename(copy, to) {
+        let _ = fs::rename(&old, to);
+        discard(copy);
+        return Err(io::Error::other(format!("couldn't replace it: {}", e)));
+    }
+    let mut summary = Summary::default();
+    remove_recursive(&old, &AtomicBool::new(false), &mut || {}, &mut summary)?;
+    match summary.failed.first() {
+        Some((_, e)) => Err(io::Error::other(format!(
+            "replaced, but {} is left over: {}",
+            old.display(),
+            e
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn discard(path: &Path) {
+    let _ = remove_recursive(
+        path,
+        &AtomicBool::new(false),
+        &mut || {},
+        &mut Summary::default(),
+    );
+}
+
+fn copy_recursive(
+    from: &Path,
+    to: &Path,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(),
+) -> io::Result<()> {
+    check(cancel)?;
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()), cancel, progress)?;
+        }
+    } else {
+        fs::copy(from, to)?;
+        progress();
+    }
+    Ok(())
+}
+
+/// Removes everything it can below `path`, recording what it couldn't in
+/// `summary`. Only cancelling stops it early.
+fn remove_recursive(
+    path: &Path,
+    cancel: &AtomicBool,
+    progress: &mut dyn FnMut(),
+    summary: &mut Summary,
+) -> io::Result<()> {
+    check(cancel)?;
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            summary.fail(path, e);
+            return Ok(());
+        }
+    };
+    if metadata.is_dir() {
+        let failed = summary.failed.len();
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                summary.fail(path, e);
+                return Ok(());
+            }
+        };
+        for entry in entries {
+            match entry {
+                Ok(entry) => remove_recursive(&entry.path(), cancel, progress, summary)?,
+                Err(e) => summary.fail(path, e),
+            }
+        }
+        // A directory left with undeletable contents isn't a failure of its own.
+        if summary.failed.len() == failed {
+            if let Err(e) = fs::remove_dir(path) {
+                summary.fail(path, e);
+            }
+        }
+    } else {
+        match fs::remove_file(path) {
+            Ok(()) => {
+                summary.succeeded += 1;
+                progress();
+            }
+            Err(e) => summary.fail(path, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn pasting_over_an_entry_needs_a_decision() {
+        let root = env::temp_dir().join(format!("tidy-paste-{}", process::id()));
+        let (from, to) = (root.join("src/a.txt"), root.join("a.txt"));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(&from, "new").unwrap();
+        fs::write(&to, "old").unwrap();
+        let copy = |to: &Path, overwrite| {
+            let item = CopyItem {
+                from: from.clone(),
+                to: to.to_path_buf(),
+                overwrite,
+            };
+            copy_item(&item, &AtomicBool::new(false), &mut || {})
+        };
+
+        assert!(copy(&to, false).is_err());
+        assert_eq!(fs::read_to_string(&to).unwrap(), "old");
+        let renamed = paths::free_name(&to);
+        assert_eq!(renamed, root.join("a-1.txt"));
+        copy(&renamed, false).unwrap();
+        copy(&to, true).unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "new");
+        assert!(copy(&from, true).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn a_cancelled_overwrite_keeps_the_destination() {
+        let root = env::temp_dir().join(format!("tidy-overwrite-{}", process::id()));
+        let (from, to) = (root.join("new"), root.join("old"));
+        fs::create_dir_all(&from).unwrap();
+        fs::write(from.join("a.txt"), "new").unwrap();
+        fs::create_dir_all(&to).unwrap();
+        fs::write(to.join("a.txt"), "old").unwrap();
+        let item = CopyItem {
+            from: from.clone(),
+            to: to.clone(),
+            overwrite: true,
+        };
+
+        let e = copy_item(&item, &AtomicBool::new(true), &mut || {}).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(fs::read_to_string(to.join("a.txt")).unwrap(), "old");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2);
+
+        copy_item(&item, &AtomicBool::new(false), &mut || {}).unwrap();
+        assert_eq!(fs::read_to_string(to.join("a.txt")).unwrap(), "new");
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 2);
+
+        // Into its own subtree, or over the directory holding it.
+        let inside = CopyItem {
+            from: root.clone(),
+            to: to.join("root"),
+            overwrite: false,
+        };
+        assert!(copy_item(&inside, &AtomicBool::new(false), &mut || {}).is_err());
+        let over = CopyItem {
+            from: from.join("a.txt"),
+            to: from.clone(),
+            overwrite: true,
+        };
+        assert!(copy_item(&over, &AtomicBool::new(false), &mut || {}).is_err());
+        assert!(from.join("a.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn running_a_command_can_be_cancelled() {
+        let mut echo = process::Command::new("sh");
+        echo.args(["-c", "echo first; echo last"]);
+        let last = run(&mut echo, &AtomicBool::new(false)).unwrap();
+        assert_eq!(last.as_deref(), Some("last"));
+
+        let mut sleep = process::Command::new("sleep");
+        sleep.arg("10");
+        let cancel = AtomicBool::new(true);
+        let started = std::time::Instant::now();
+        let e = run(&mut sleep, &cancel).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Interrupted);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+};
+
+use directories::{BaseDirs, ProjectDirs};
+
+pub fn home_dir() -> Option<PathBuf> {
+    BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf())
+}
+
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "tidy")
+}
+
+/// The global config file, e.g. `$XDG_CONFIG_HOME/tidy/config` on Linux.
+pub fn config_file() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.config_dir().join("config"))
+}
+
+/// The tag database, e.g. `$XDG_DATA_HOME/tidy/tidy.db` on Linux.
+pub fn database_file() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("tidy.db"))
+}
+
+/// Debug log written when only `RUST_LOG` is set, next to the database.
+pub fn log_file() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("tidy.log"))
+}
+
+/// Table view columns, saved next to the database.
+pub fn view_file() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().join("view"))
+}
+
+/// Expands a leading `~` to the home directory.
+pub fn expand_tilde(input: &str) -> PathBuf {
+    match input.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', MAIN_SEPARATOR].as_ref()) => {
+            match home_dir() {
+                Some(home) => home.join(rest.trim_start_matches(['/', MAIN_SEPARATOR].as_ref())),
+                None => PathBuf::from(input),
+            }
+        }
+        _ => PathBuf::from(input),
+    }
+}
+
+/// Completes the last component of `input` against the entries of its parent
+/// directory. Returns the extended input and the candidate names that matched.
+pub fn complete(input: &str) -> (String, Vec<String>) {
+    let split = input
+        .rfind(['/', MAIN_SEPARATOR].as_ref())
+        .map_or(0, |i| i + 1);
+    let (parent, prefix) = input.split_at(split);
+    let directory = match parent {
+        "" => PathBuf::from("."),
+        parent => expand_tilde(parent),
+    };
+
+    let mut candidates: Vec<String> = fs::read_dir(&directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) {
+                return None;
+            }
+            match entry.path().is_dir() {
+                true => Some(format!("{}{}", name, MAIN_SEPARATOR)),
+                false => Some(name),
+            }
+        })
+        .collect();
+    candidates.sort();
+
+    let completed = match candidates.split_first() {
+        Some((first, rest)) => rest.iter().fold(first.as_str(), |common, name| {
+            let len = common
+                .char_indices()
+                .zip(name.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((i, a), _)| i + a.len_utf8());
+            &common[..len]
+        }),
+        None => prefix,
+    };
+    (format!("{}{}", parent, completed), candidates)
+}
+
+/// Checks that `path` is a directory tidy can list.
+pub fn check_dir(path: &Path) -> Result<PathBuf, String> {
+    let path = path.canonicalize().map_err(|e| e.to_string())?;
+    fs::read_dir(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// The closest ancestor of `path` that still exists, or the home directory,
+/// for when `path` is removed while it's being shown.
+pub fn fallback_dir(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(1)
+        .find(|ancestor| check_dir(ancestor).is_ok())
+        .map(Path::to_path_buf)
+        .or_else(home_dir)
+}
+
+/// Checks that `name` names an entry in place, rather than a path that
+/// would move it somewhere else.
+pub fn check_name(name: &str) -> Result<(), String> {
+    match name {
+        "" => Err("Type a name".to_string()),
+        "." | ".." => Err(format!("{:?} isn't a name", name)),
+        _ if name.contains(['/', MAIN_SEPARATOR].as_ref()) => {
+            Err("Names can't contain path separators".to_string())
+        }
+        _ if name.contains('\0') => Err("Names can't contain NUL".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Renames `from` to `to` unless something else is already there. On
+/// case-insensitive filesystems `to` may be `from` spelled in another case,
+/// which a plain rename can leave as it was, so that goes through a
+/// temporary name.
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(to).is_err() {
+        return fs::rename(from, to);
+    }
+    if !case_only(from, to) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    rename_through_temp(from, to)
+}
+
+/// Whether `to` is `from` with only its name's case changed, and both lead
+/// to the same entry, as they do when the filesystem ignores case.
+fn case_only(from: &Path, to: &Path) -> bool {
+    let names = (from.file_name(), to.file_name());
+    let same_name = match names {
+        (Some(a), Some(b)) => {
+            a != b && a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+        }
+        _ => false,
+    };
+    same_name && same_entry(from, to)
+}
+
+#[cfg(unix)]
+fn same_entry(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_entry(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn rename_through_temp(from: &Path, to: &Path) -> io::Result<()> {
+    let name = from.file_name().unwrap_or_default().to_string_lossy();
+    let temp = free_name(&from.with_file_name(format!(".{}.tidy-rename", name)));
+    fs::rename(from, &temp)?;
+    if let Err(e) = fs::rename(&temp, to) {
+        // Put it back rather than leave it under the temporary name.
+        let _ = fs::rename(&temp, from);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// `path`, or the first of `name-1.ext`, `name-2.ext`… beside it that
+/// doesn't exist yet.
+pub fn free_name(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut candidate = path.to_path_buf();
+    let mut n = 0;
+    while fs::symlink_metadata(&candidate).is_ok() {
+        n += 1;
+        candidate = path.with_file_name(format!("{}-{}{}", stem, n, extension));
+    }
+    candidate
+}
+
+/// Every file below `directory`, without following symlinked directories.
+pub fn walk(directory: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    walk_each(directory, usize::MAX, &mut |file| {
+        files.push(file);
+        true
+    });
+    files
+}
+
+/// Hands each file up to `max_depth` levels below `directory` to `found`,
+/// until it returns `false`. Symlinked directories aren't followed.
+pub fn walk_each(directory: &Path, max_depth: usize, found: &mut dyn FnMut(PathBuf) -> bool) {
+    let mut pending = vec![(directory.to_path_buf(), 1)];
+    while let Some((directory, depth)) = pending.pop() {
+        for entry in fs::read_dir(&directory).into_iter().flatten().flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {
+                    if depth < max_depth {
+                        pending.push((entry.path(), depth + 1));
+                    }
+                }
+                Ok(_) => {
+                    if !found(entry.path()) {
+                        return;
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Boolean expressions over tags, like `receipts and (2020 or 2021) and not
+//! paid`. Terms next to each other are and-ed, so `receipts 2021` works too.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn parse(input: &str) -> Result<TagExpr, String> {
+        let tokens = tokenize(input);
+        let mut parser = Parser { tokens, at: 0 };
+        if parser.tokens.is_empty() {
+            return Err("Empty tag expression".to_string());
+        }
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected '{}'", token)),
+        }
+    }
+
+    /// Whether an entry with `tags` is one the expression asks for.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.contains(tag),
+            TagExpr::Not(expr) => !expr.matches(tags),
+            TagExpr::And(a, b) => a.matches(tags) && b.matches(tags),
+            TagExpr::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+}
+
+impl fmt::Display for TagExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagExpr::Tag(tag) => write!(f, "{}", tag),
+            TagExpr::Not(expr) => write!(f, "not {}", expr),
+            TagExpr::And(a, b) => write!(f, "({} and {})", a, b),
+            TagExpr::Or(a, b) => write!(f, "({} or {})", a, b),
+        }
+    }
+}
+
+/// Words, with parentheses split off even when they're not spaced out.
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in input.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.at).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("or") {
+            self.next();
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek() {
+                None | Some("or") | Some(")") => return Ok(expr),
+                Some("and") => {
+                    self.next();
+                }
+                Some(_) => {}
+            }
+            expr = TagExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<TagExpr, String> {
+        match self.next().as_deref() {
+            Some("not") => Ok(TagExpr::Not(Box::new(self.not()?))),
+            Some("(") => {
+                let expr = self.or()?;
+                match self.next().as_deref() {
+                    Some(")") => Ok(expr),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            }
+            Some(token @ ")") | Some(token @ "and") | Some(token @ "or") => {
+                Err(format!("Unexpected '{}'", token))
+            }
+            Some(tag) => Ok(TagExpr::Tag(tag.to_string())),
+            None => Err("Expression ends early".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_binds_tighter_than_and_tighter_than_or() {
+        let expr = TagExpr::parse("a or b c and not (d or e)").unwrap();
+        assert_eq!(expr.to_string(), "(a or ((b and c) and not (d or e)))");
+
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        assert!(expr.matches(&tags(&["a", "d"])));
+        assert!(expr.matches(&tags(&["b", "c"])));
+        assert!(!expr.matches(&tags(&["b", "c", "e"])));
+        assert!(!expr.matches(&tags(&["b"])));
+
+        assert!(TagExpr::parse("(a or b").is_err());
+        assert!(TagExpr::parse("a and").is_err());
+        assert!(TagExpr::parse("a)").is_err());
+        assert!(TagExpr::parse("").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Hands files to the platform's graphical file manager or default
+//! applications, for when the TUI isn't the right tool.
+
+use std::{
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Opens the file manager with `path` selected, or at least its directory
+/// where selecting isn't supported.
+pub fn reveal(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        spawn(Command::new("open").arg("-R").arg(path))
+    } else if cfg!(windows) {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        spawn(Command::new("explorer").arg(select))
+    } else {
+        show_items(path).or_else(|_| {
+            let directory = path.parent().unwrap_or(path);
+            spawn(Command::new("xdg-open").arg(directory))
+        })
+    }
+}
+
+/// Opens `path` with the application the desktop associates with it.
+pub fn open(path: &Path) -> io::Result<()> {
+    if cfg!(target_os = "macos") {
+        spawn(Command::new("open").arg(path))
+    } else if cfg!(windows) {
+        spawn(Command::new("cmd").args(["/C", "start", ""]).arg(path))
+    } else {
+        spawn(Command::new("xdg-open").arg(path))
+    }
+}
+
+/// Opens `path` with `app`, a program optionally followed by arguments that
+/// go before the path, like `code --new-window`.
+pub fn open_with(app: &str, path: &Path) -> io::Result<()> {
+    let mut words = app.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no program given"))?;
+    spawn(Command::new(program).args(words).arg(path))
+}
+
+/// Asks a freedesktop file manager over D-Bus to select `path`.
+fn show_items(path: &Path) -> io::Result<()> {
+    let status = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--print-reply",
+            "--dest=org.freedesktop.FileManager1",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", file_uri(path)))
+        .arg("string:")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other("no file manager answered")),
+    }
+}
+
+/// A `file://` URI for `path`, escaping everything but unreserved characters
+/// and separators.
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Starts a program without waiting for it or letting it draw over the TUI.
+fn spawn(command: &mut Command) -> io::Result<()> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(drop)
+}
@@ -0,0 +1,302 @@
+//! Where indexed files and their tags are kept. The UI only talks to the
+//! `Storage` trait, so the SQLite backend in `db` can be swapped out.
+
+use std::{
+    collections::HashSet,
+    error::Error,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// How many entries reindexing a directory added and removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reindexed {
+    pub added: usize,
+    pub removed: usize,
+}
+
+pub trait Storage {
+    /// Records `files` under `directory`, calling `progress` with the number
+    /// stored so far.
+    fn index_directory(
+        &self,
+        directory: &str,
+        files: &[PathBuf],
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<()>;
+    /// Brings the records of `directory` in line with what's on disk in one
+    /// go: new entries are added, vanished ones removed and the metadata of
+    /// the rest refreshed.
+    fn reindex_directory(&self, directory: &Path) -> Result<Reindexed>;
+    /// Adds `outcome`, like "deleted 40 files", to the history of
+    /// `directory`, as having happened just now.
+    fn record_operation(&self, directory: &Path, outcome: &str) -> Result<()>;
+    /// The latest entry of `directory`'s history and when it happened.
+    fn last_operation(&self, directory: &Path) -> Result<Option<(SystemTime, String)>>;
+    /// When `directory` was last indexed, if ever.
+    fn indexed_at(&self, directory: &Path) -> Result<Option<SystemTime>>;
+    /// When `path` was first indexed, if it has been since tidy started
+    /// recording that.
+    fn first_seen(&self, path: &Path) -> Result<Option<SystemTime>>;
+    fn tags_for(&self, path: &Path) -> Result<Vec<String>>;
+    fn add_tags(&self, path: &Path, tags: &[String]) -> Result<()>;
+    /// Applies every tag to every file, all or nothing.
+    fn add_tags_batch(&self, paths: &[PathBuf], tags: &[String]) -> Result<()>;
+    fn remove_tag(&self, path: &Path, tag: &str) -> Result<()>;
+    /// Every path that has tags, with them, in path order.
+    fn all_tags(&self) -> Result<Vec<(PathBuf, Vec<String>)>>;
+    /// Entries directly inside `directory` that have at least one tag.
+    fn tagged_in(&self, directory: &Path) -> Result<HashSet<PathBuf>>;
+    /// Up to `limit` tags applied to other entries of `directory`, most used
+    /// first.
+    fn sibling_tags(&self, directory: &Path, limit: usize) -> Result<Vec<String>>;
+    /// Marks `path` as one tidy must not delete, or lifts that again.
+    fn set_protected(&self, path: &Path, protected: bool) -> Result<()>;
+    /// Protected paths that are `path` itself or lie below it.
+    fn protected_in(&self, path: &Path) -> Result<Vec<PathBuf>>;
+    fn note(&self, path: &Path) -> Result<Option<String>>;
+    /// Attaches a free-text note to `path`, or removes it when empty.
+    fn set_note(&self, path: &Path, note: &str) -> Result<()>;
+    /// Carries the records of `from`, and of anything below it, over to `to`
+    /// after it's been renamed.
+    fn move_path(&self, from: &Path, to: &Path) -> Result<()>;
+    /// A preference saved with `set_setting`, if there is one.
+    fn setting(&self, key: &str) -> Result<Option<String>>;
+    fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+}
+
+#[cfg(test)]
+pub use memory::MemoryStorage;
+
+#[cfg(test)]
+mod memory {
+    use std::{
+        cell::RefCell,
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        fs,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    };
+
+    use super::{Reindexed, Result, Storage};
+
+    /// Keeps everything in maps, for tests that shouldn't touch a database.
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        pub dirs: RefCell<HashMap<String, Vec<PathBuf>>>,
+        pub tags: RefCell<BTreeMap<PathBuf, BTreeSet<String>>>,
+        pub seen: RefCell<HashMap<PathBuf, SystemTime>>,
+        pub indexed: RefCell<HashMap<String, SystemTime>>,
+        pub protected: RefCell<BTreeSet<PathBuf>>,
+        pub settings: RefCell<HashMap<String, String>>,
+        pub notes: RefCell<HashMap<PathBuf, String>>,
+        pub operations: RefCell<Vec<(PathBuf, SystemTime, String)>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn index_directory(
+            &self,
+            directory: &str,
+            files: &[PathBuf],
+            progress: &mut dyn FnMut(usize),
+        ) -> Result<()> {
+            let mut dirs = self.dirs.borrow_mut();
+            let indexed = dirs.entry(directory.to_string()).or_default();
+            let now = SystemTime::now();
+            for (i, file) in files.iter().enumerate() {
+                self.seen.borrow_mut().entry(file.clone()).or_insert(now);
+                if !indexed.contains(file) {
+                    indexed.push(file.clone());
+                }
+                progress(i + 1);
+            }
+            self.indexed.borrow_mut().insert(directory.to_string(), now);
+            Ok(())
+        }
+
+        fn reindex_directory(&self, directory: &Path) -> Result<Reindexed> {
+            let on_disk: Vec<PathBuf> = fs::read_dir(directory)?
+                .flatten()
+                .map(|entry| entry.path())
+                .collect();
+            let key = directory.to_string_lossy().into_owned();
+            let mut dirs = self.dirs.borrow_mut();
+            let indexed = dirs.entry(key.clone()).or_default();
+            let before = indexed.len();
+            indexed.retain(|file| on_disk.contains(file));
+            let mut reindexed = Reindexed {
+                added: 0,
+                removed: before - indexed.len(),
+            };
+            let now = SystemTime::now();
+            for file in on_disk {
+                if !indexed.contains(&file) {
+                    self.seen.borrow_mut().entry(file.clone()).or_insert(now);
+                    indexed.push(file);
+                    reindexed.added += 1;
+                }
+            }
+            self.indexed.borrow_mut().insert(key, now);
+            Ok(reindexed)
+        }
+
+        fn record_operation(&self, directory: &Path, outcome: &str) -> Result<()> {
+            self.operations.borrow_mut().push((
+                directory.to_path_buf(),
+                SystemTime::now(),
+                outcome.to_string(),
+            ));
+            Ok(())
+        }
+
+        fn last_operation(&self, directory: &Path) -> Result<Option<(SystemTime, String)>> {
+            Ok(self
+                .operations
+                .borrow()
+                .iter()
+                .rev()
+                .find(|(path, _, _)| path == directory)
+                .map(|(_, at, outcome)| (*at, outcome.clone())))
+        }
+
+        fn indexed_at(&self, directory: &Path) -> Result<Option<SystemTime>> {
+            Ok(self
+                .indexed
+                .borrow()
+                .get(&*directory.to_string_lossy())
+                .copied())
+        }
+
+        fn first_seen(&self, path: &Path) -> Result<Option<SystemTime>> {
+            Ok(self.seen.borrow().get(path).copied())
+        }
+
+        fn tags_for(&self, path: &Path) -> Result<Vec<String>> {
+            Ok(self
+                .tags
+                .borrow()
+                .get(path)
+                .map(|tags| tags.iter().cloned().collect())
+                .unwrap_or_default())
+        }
+
+        fn add_tags(&self, path: &Path, tags: &[String]) -> Result<()> {
+            self.tags
+                .borrow_mut()
+                .entry(path.to_path_buf())
+                .or_default()
+                .extend(tags.iter().cloned());
+            Ok(())
+        }
+
+        fn add_tags_batch(&self, paths: &[PathBuf], tags: &[String]) -> Result<()> {
+            for path in paths {
+                self.add_tags(path, tags)?;
+            }
+            Ok(())
+        }
+
+        fn remove_tag(&self, path: &Path, tag: &str) -> Result<()> {
+            if let Some(tags) = self.tags.borrow_mut().get_mut(path) {
+                tags.remove(tag);
+            }
+            Ok(())
+        }
+
+        fn all_tags(&self) -> Result<Vec<(PathBuf, Vec<String>)>> {
+            Ok(self
+                .tags
+                .borrow()
+                .iter()
+                .filter(|(_, tags)| !tags.is_empty())
+                .map(|(path, tags)| (path.clone(), tags.iter().cloned().collect()))
+                .collect())
+        }
+
+        fn tagged_in(&self, directory: &Path) -> Result<HashSet<PathBuf>> {
+            Ok(self
+                .tags
+                .borrow()
+                .iter()
+                .filter(|(path, tags)| path.parent() == Some(directory) && !tags.is_empty())
+                .map(|(path, _)| path.clone())
+                .collect())
+        }
+
+        fn sibling_tags(&self, directory: &Path, limit: usize) -> Result<Vec<String>> {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            for (path, tags) in self.tags.borrow().iter() {
+                if path.parent() == Some(directory) {
+                    for tag in tags {
+                        *counts.entry(tag.clone()).or_default() += 1;
+                    }
+                }
+            }
+            let mut tags: Vec<_> = counts.into_iter().collect();
+            tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            Ok(tags.into_iter().take(limit).map(|(tag, _)| tag).collect())
+        }
+
+        fn set_protected(&self, path: &Path, protected: bool) -> Result<()> {
+            let mut set = self.protected.borrow_mut();
+            match protected {
+                true => set.insert(path.to_path_buf()),
+                false => set.remove(path),
+            };
+            Ok(())
+        }
+
+        fn protected_in(&self, path: &Path) -> Result<Vec<PathBuf>> {
+            Ok(self
+                .protected
+                .borrow()
+                .iter()
+                .filter(|protected| protected.starts_with(path))
+                .cloned()
+                .collect())
+        }
+
+        fn note(&self, path: &Path) -> Result<Option<String>> {
+            Ok(self.notes.borrow().get(path).cloned())
+        }
+
+        fn set_note(&self, path: &Path, note: &str) -> Result<()> {
+            let mut notes = self.notes.borrow_mut();
+            match note.is_empty() {
+                true => notes.remove(path),
+                false => notes.insert(path.to_path_buf(), note.to_string()),
+            };
+            Ok(())
+        }
+
+        fn move_path(&self, from: &Path, to: &Path) -> Result<()> {
+            let moved = |path: &Path| path.strip_prefix(from).ok().map(|rest| to.join(rest));
+            let mut tags = self.tags.borrow_mut();
+            for (path, set) in std::mem::take(&mut *tags) {
+                tags.insert(moved(&path).unwrap_or(path), set);
+            }
+            let mut notes = self.notes.borrow_mut();
+            for (path, note) in std::mem::take(&mut *notes) {
+                notes.insert(moved(&path).unwrap_or(path), note);
+            }
+            let mut protected = self.protected.borrow_mut();
+            for path in std::mem::take(&mut *protected) {
+                protected.insert(moved(&path).unwrap_or(path));
+            }
+            Ok(())
+        }
+
+        fn setting(&self, key: &str) -> Result<Option<String>> {
+            Ok(self.settings.borrow().get(key).cloned())
+        }
+
+        fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+            self.settings
+                .borrow_mut()
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+    }
+}
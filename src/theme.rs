@@ -0,0 +1,53 @@
+//! Styles used across the UI, collapsed to plain text attributes when colour
+//! is turned off with `--no-color` or `NO_COLOR`.
+
+use tui::style::{Color, Modifier, Style};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    pub color: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme { color: true }
+    }
+}
+
+impl Theme {
+    /// Text in `color`, or unstyled without colour.
+    pub fn fg(self, color: Color) -> Style {
+        match self.color {
+            true => Style::default().fg(color),
+            false => Style::default(),
+        }
+    }
+
+    /// Text that has to stand out from its neighbours, underlined without
+    /// colour.
+    pub fn accent(self, color: Color) -> Style {
+        match self.color {
+            true => Style::default().fg(color).add_modifier(Modifier::BOLD),
+            false => Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        }
+    }
+
+    /// A label on a `color` background, left plain without colour.
+    pub fn pill(self, color: Color) -> Style {
+        match self.color {
+            true => Style::default().bg(color).fg(Color::Black),
+            false => Style::default(),
+        }
+    }
+
+    /// The selected row of a list.
+    pub fn highlight(self) -> Style {
+        match self.color {
+            true => Style::default()
+                .bg(Color::Yellow)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+            false => Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+        }
+    }
+}
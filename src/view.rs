@@ -0,0 +1,230 @@
+//! Columns of the table view, remembered between runs in the data directory.
+//!
+//! The file uses the same `key = value` format as the config, e.g.
+//!
+//! ```text
+//! name = 40
+//! size = 9
+//! hidden = tags
+//! ```
+
+use std::{fs, io};
+
+use crate::{config, paths};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnKind {
+    Name,
+    Size,
+    Modified,
+    Tags,
+}
+
+impl ColumnKind {
+    const ALL: [ColumnKind; 4] = [
+        ColumnKind::Name,
+        ColumnKind::Size,
+        ColumnKind::Modified,
+        ColumnKind::Tags,
+    ];
+
+    pub fn parse(key: &str) -> Option<ColumnKind> {
+        ColumnKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.key() == key)
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            ColumnKind::Name => "name",
+            ColumnKind::Size => "size",
+            ColumnKind::Modified => "modified",
+            ColumnKind::Tags => "tags",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ColumnKind::Name => "Name",
+            ColumnKind::Size => "Size",
+            ColumnKind::Modified => "Modified",
+            ColumnKind::Tags => "Tags",
+        }
+    }
+
+    fn default_width(self) -> u16 {
+        match self {
+            ColumnKind::Name => 30,
+            ColumnKind::Size => 9,
+            ColumnKind::Modified => 16,
+            ColumnKind::Tags => 20,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    pub kind: ColumnKind,
+    pub width: u16,
+    pub hidden: bool,
+}
+
+const MIN_WIDTH: u16 = 4;
+const MAX_WIDTH: u16 = 200;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Columns {
+    pub columns: Vec<Column>,
+    /// The column resize keys apply to.
+    pub focused: usize,
+}
+
+impl Default for Columns {
+    fn default() -> Columns {
+        Columns {
+            columns: ColumnKind::ALL
+                .iter()
+                .map(|&kind| Column {
+                    kind,
+                    width: kind.default_width(),
+                    hidden: false,
+                })
+                .collect(),
+            focused: 0,
+        }
+    }
+}
+
+impl Columns {
+    pub fn load() -> Columns {
+        paths::view_file()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| Columns::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = match paths::view_file() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_string())
+    }
+
+    fn parse(contents: &str) -> Columns {
+        let mut columns = Columns::default();
+        for (key, value) in config::entries(contents) {
+            if key == "hidden" {
+                for hidden in config::list(value) {
+                    if let Some(column) = columns.find(hidden) {
+                        column.hidden = true;
+                    }
+                }
+            } else if let (Some(column), Ok(width)) = (columns.find(key), value.parse::<u16>()) {
+                column.width = width.clamp(MIN_WIDTH, MAX_WIDTH);
+            }
+        }
+        columns
+    }
+
+    fn find(&mut self, key: &str) -> Option<&mut Column> {
+        self.columns
+            .iter_mut()
+            .find(|column| column.kind.key() == key)
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = (usize, &Column)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| !column.hidden)
+    }
+
+    /// The columns `only` names, or the visible ones when it's empty, e.g.
+    /// for a directory whose `.tidy` picks its own.
+    pub fn visible_in<'a>(
+        &'a self,
+        only: &'a [ColumnKind],
+    ) -> impl Iterator<Item = (usize, &'a Column)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(move |(_, column)| match only.is_empty() {
+                true => !column.hidden,
+                false => only.contains(&column.kind),
+            })
+    }
+
+    /// Moves focus to the next (or previous) column, wrapping around.
+    pub fn focus(&mut self, forward: bool) {
+        let len = self.columns.len();
+        self.focused = match forward {
+            true => (self.focused + 1) % len,
+            false => (self.focused + len - 1) % len,
+        };
+    }
+
+    pub fn resize(&mut self, delta: i16) {
+        let column = &mut self.columns[self.focused];
+        column.width =
+            (column.width as i16 + delta).clamp(MIN_WIDTH as i16, MAX_WIDTH as i16) as u16;
+    }
+
+    /// Shows or hides the column at `index`, always leaving one visible.
+    pub fn toggle(&mut self, index: usize) {
+        let visible = self.visible().count();
+        if let Some(column) = self.columns.get_mut(index) {
+            if column.hidden || visible > 1 {
+                column.hidden = !column.hidden;
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Columns {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for column in &self.columns {
+            writeln!(f, "{} = {}", column.kind.key(), column.width)?;
+        }
+        let hidden: Vec<_> = self
+            .columns
+            .iter()
+            .filter(|column| column.hidden)
+            .map(|column| column.kind.key())
+            .collect();
+        writeln!(f, "hidden = {}", hidden.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_round_trip() {
+        let mut columns = Columns::default();
+        columns.focus(true);
+        columns.resize(5);
+        columns.toggle(3);
+        let parsed = Columns::parse(&columns.to_string());
+        assert_eq!(parsed.columns, columns.columns);
+        assert_eq!(parsed.columns[1].width, 14);
+        assert!(parsed.columns[3].hidden);
+    }
+
+    #[test]
+    fn last_visible_column_stays() {
+        let mut columns = Columns::default();
+        for index in 0..4 {
+            columns.toggle(index);
+        }
+        assert_eq!(columns.visible().count(), 1);
+        columns.focused = 0;
+        columns.resize(-100);
+        assert_eq!(columns.columns[0].width, MIN_WIDTH);
+    }
+}